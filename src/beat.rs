@@ -0,0 +1,259 @@
+use std::marker::PhantomData;
+
+use bevy::{prelude::*, ecs::query::WorldQuery};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::Character,
+    nav::Navigation,
+    status::Statuses,
+    song::SongPlayback,
+    tween::{Curve, Ease, Keyframe, Tween, TweenType},
+};
+
+pub struct BeatPlugin;
+impl Plugin for BeatPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_type::<Tween>()
+            .register_type::<TweenType>()
+            .register_type::<Keyframe>()
+            .register_type::<Ease>()
+            .register_type::<Curve>()
+            .register_type::<Beat>()
+            .register_type::<BeatScale>()
+            .register_type::<BeatSpin>()
+            .configure_sets(Update, (BeatSet::Prepare, BeatSet::Behave, BeatSet::Apply).chain())
+            .add_plugins((
+                BeatBehaviorPlugin::<BeatScale>::default(),
+                BeatBehaviorPlugin::<BeatSpin>::default(),
+            ));
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BeatSet {
+    Prepare,
+    Behave,
+    Apply,
+}
+
+/// When a behavior fires (every `freq` beats, on beat `on_beat`) and how its
+/// strength evolves over the course of that beat.
+#[derive(Clone, Debug, Reflect, Serialize, Deserialize)]
+#[reflect(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Beat {
+    pub tween: Tween,
+    pub freq: usize,
+    pub on_beat: usize,
+}
+
+impl Default for Beat {
+    fn default() -> Self {
+        Self { tween: Tween::default(), freq: 1, on_beat: 0 }
+    }
+}
+
+impl Beat {
+    /// Whether the behavior fires on beat number `beat_count`. Asking about
+    /// `song.beat_count + 1` looks ahead to what happens on the next beat.
+    /// A `freq` of zero, as authored data can have, counts as every beat.
+    pub fn is_active(&self, beat_count: usize) -> bool {
+        beat_count % self.freq.max(1) == self.on_beat
+    }
+
+    /// Fires twice as often, keeping to the same beats where it can.
+    pub fn double_time(&mut self) {
+        if self.freq > 1 {
+            self.freq /= 2;
+            self.on_beat %= self.freq;
+        }
+    }
+
+    pub fn sample(&self, song: &SongPlayback) -> Option<f32> {
+        self.is_active(song.beat_count).then(|| self.tween.tween(song.bpm_timer.percent()))
+    }
+}
+
+pub struct BeatContext {
+    pub character: Vec3,
+    /// Where to head to reach the character: the next waypoint of the
+    /// entity's [`Navigation`], or the character itself
+    pub target: Vec3,
+    pub beat: usize,
+}
+
+impl BeatContext {
+    pub fn new(character: Vec3, next: Option<Vec2>, beat: usize) -> Self {
+        let target = next.map_or(character, |next| next.extend(character.z));
+        Self { character, target, beat }
+    }
+}
+
+pub type BeatTarget<'w, B> = <<B as BeatBehavior>::Target as WorldQuery>::Item<'w>;
+
+/// A component that does something to its entity in time with the music.
+///
+/// Beat gating and tween evaluation are handled by [`BeatBehaviorPlugin`], so
+/// implementors only describe what happens on and off the beat.
+pub trait BeatBehavior: Component + Sized {
+    type Target: WorldQuery + 'static;
+
+    /// Steering forces of a higher priority replace those of a lower one, see
+    /// [`Steering`](crate::steering::Steering).
+    const PRIORITY: u8 = 0;
+
+    /// Whether the behavior moves its entity. Its tween's value is then a
+    /// speed, which [`StatusKind::Slow`](crate::status::StatusKind::Slow)
+    /// brings down, and only freezing stops it.
+    const MOVEMENT: bool = false;
+
+    fn beat(&self) -> &Beat;
+    fn on_beat(&mut self, value: f32, context: &BeatContext, target: BeatTarget<Self>);
+    fn off_beat(&mut self, context: &BeatContext, target: BeatTarget<Self>);
+}
+
+pub struct BeatBehaviorPlugin<B>(PhantomData<B>);
+impl<B> Default for BeatBehaviorPlugin<B> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+impl<B: BeatBehavior> Plugin for BeatBehaviorPlugin<B> {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_systems(Update, run_beat_behavior::<B>.in_set(BeatSet::Behave));
+    }
+}
+
+fn run_beat_behavior<B: BeatBehavior>(
+    mut behaviors: Query<(&mut B, B::Target, Navigation, Option<&Statuses>), Without<Character>>,
+    character: Query<&Transform, With<Character>>,
+    song: Res<SongPlayback>,
+) {
+    let character = character.single().translation;
+    for (mut behavior, target, navigation, statuses) in behaviors.iter_mut() {
+        let context = BeatContext::new(character, navigation.next(), song.beat_count);
+        let suppressed = statuses.is_some_and(|statuses| {
+            if B::MOVEMENT { statuses.suppresses_movement() } else { statuses.suppresses_actions() }
+        });
+        let scale = if B::MOVEMENT { statuses.map_or(1.0, Statuses::speed_scale) } else { 1.0 };
+        match behavior.beat().sample(&song).filter(|_| !suppressed).map(|value| value * scale) {
+            Some(value) => behavior.on_beat(value, &context, target),
+            None => behavior.off_beat(&context, target),
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatScale {
+    pub beat: Beat,
+}
+impl BeatBehavior for BeatScale {
+    type Target = &'static mut Transform;
+
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
+
+    fn on_beat(&mut self, size: f32, _context: &BeatContext, mut transform: BeatTarget<Self>) {
+        transform.scale = Vec3::new(size, size, 1.0);
+    }
+
+    fn off_beat(&mut self, _context: &BeatContext, mut transform: BeatTarget<Self>) {
+        transform.scale = Vec3::ONE;
+    }
+}
+
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatSpin {
+    pub beat: Beat,
+}
+impl BeatBehavior for BeatSpin {
+    type Target = &'static mut Transform;
+
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
+
+    fn on_beat(&mut self, radians: f32, _context: &BeatContext, mut transform: BeatTarget<Self>) {
+        transform.rotation = Quat::from_rotation_z(radians);
+    }
+
+    fn off_beat(&mut self, _context: &BeatContext, mut transform: BeatTarget<Self>) {
+        transform.rotation = Quat::from_rotation_z(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        bullet::{BeatShooter, BulletPattern},
+        monster::BeatChase,
+        status::StatusKind,
+        steering::Steering,
+    };
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> (String, String) {
+        let json = serde_json::to_string(value).unwrap();
+        let back: T = serde_json::from_str(&json).unwrap();
+        (json, serde_json::to_string(&back).unwrap())
+    }
+
+    #[test]
+    fn beat_round_trips() {
+        let beat = Beat {
+            tween: Tween { ttype: TweenType::Keyframes(vec![Keyframe::new(0.5, 2.0, Ease::Out(Curve::Back))]), a: 1.5, ease: Ease::InOut(Curve::Expo), ..default() },
+            freq: 4,
+            on_beat: 3,
+        };
+        let (json, back) = round_trip(&beat);
+        assert_eq!(json, back);
+    }
+
+    #[test]
+    fn beat_shooter_round_trips() {
+        let beat = Beat { tween: Tween { ttype: TweenType::Square, a: 90.0, b: 30.0, ..default() }, freq: 2, on_beat: 1 };
+        let shooter = BeatShooter::new(beat, BulletPattern::Spiral { arms: 3, step: 0.4 }, 4);
+        let (json, back) = round_trip(&shooter);
+        assert_eq!(json, back);
+    }
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let beat: Beat = serde_json::from_str(r#"{"on_beat": 1}"#).unwrap();
+        assert_eq!((beat.freq, beat.on_beat), (1, 1));
+    }
+
+    #[test]
+    fn zero_freq_fires_every_beat() {
+        let beat: Beat = serde_json::from_str(r#"{"freq": 0}"#).unwrap();
+        assert!(beat.is_active(0));
+        assert!(beat.is_active(7));
+    }
+
+    /// The velocity a chasing monster with `status` on it steers at.
+    fn chase_with(status: StatusKind) -> Vec2 {
+        let mut world = World::new();
+        world.insert_resource(SongPlayback { bpm_timer: Timer::from_seconds(0.5, TimerMode::Repeating), beat_count: 0, markers: Vec::new() });
+        world.spawn((Character, Transform::from_xyz(100.0, 0.0, 0.0)));
+        let mut statuses = Statuses::default();
+        statuses.add(status, 4, 0);
+        let chase = BeatChase { beat: Beat { tween: Tween { a: 60.0, b: 60.0, ..default() }, freq: 1, on_beat: 0 } };
+        let monster = world.spawn((chase, Transform::default(), Steering::default(), statuses)).id();
+
+        world.run_system_once(run_beat_behavior::<BeatChase>);
+        world.get_mut::<Steering>(monster).unwrap().combined()
+    }
+
+    #[test]
+    fn stunned_monsters_still_move() {
+        assert_eq!(chase_with(StatusKind::Stun), Vec2::new(60.0, 0.0));
+        assert_eq!(chase_with(StatusKind::Freeze), Vec2::ZERO);
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
+use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    beat::{Beat, BeatBehavior, BeatBehaviorPlugin, BeatContext, BeatSet, BeatTarget},
+    character::{Character, PlayerHit},
+    collision,
+    ldtk::{EntityInstance, TilesetDef},
+    song::{SongPlayback, BEATS_PER_BAR},
+    terrain::{RegisterLdtkEntity, TerrainCollider},
+};
+
+pub struct BeatTerrainPlugin;
+impl Plugin for BeatTerrainPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_ldtk_entity("Gate", |commands, instance| {
+                let every = field_usize(instance, "every_bars", 4) * BEATS_PER_BAR;
+                let open = field_usize(instance, "open_bars", 1) * BEATS_PER_BAR;
+                commands.insert(BeatGate::bundle(every, open, instance.size()));
+            })
+            .register_ldtk_entity("Barrier", |commands, instance| {
+                let every = field_usize(instance, "every", 2);
+                let up = field_usize(instance, "up", 1);
+                commands.insert(BeatGate::bundle(every, every.saturating_sub(up), instance.size()));
+            })
+            .add_plugins(BeatBehaviorPlugin::<TilePulse>::default())
+            .add_systems(Update, (TileHazard::system, BeatGate::system).in_set(BeatSet::Behave));
+    }
+}
+
+fn field_usize(instance: &EntityInstance, identifier: &str, default: usize) -> usize {
+    instance.field(identifier).and_then(|value| value.as_u64()).map_or(default, |value| value as usize)
+}
+
+/// What a tile does with the beat, read from the custom data LDtk keeps for
+/// each tile of a tileset. The data is JSON like
+/// `{"pulse": {"tween": {...}}, "hazard": {"damage": 10, "beat": {"freq": 4}}}`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TileBeat {
+    /// Brightness of the tile over its beats
+    pub pulse: Option<Beat>,
+    pub hazard: Option<TileHazard>,
+}
+
+impl TileBeat {
+    /// The tiles of `tileset` with beat metadata, by tile id.
+    pub fn from_tileset(tileset: &TilesetDef) -> HashMap<u32, TileBeat> {
+        tileset.custom_data
+            .iter()
+            .filter_map(|tile| match serde_json::from_str(&tile.data) {
+                Ok(beat) => Some((tile.tile_id, beat)),
+                Err(error) => {
+                    warn!("Bad custom data on tile {} of {}: {error}", tile.tile_id, tileset.identifier);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Adds the behaviors to a tile whose color is normally `color`.
+    pub fn insert(&self, commands: &mut EntityCommands, color: Color) {
+        if let Some(beat) = &self.pulse {
+            commands.insert(TilePulse { beat: beat.clone(), base: color });
+        }
+        if let Some(hazard) = &self.hazard {
+            commands.insert(hazard.clone());
+        }
+    }
+}
+
+/// Scales the brightness of a tile by the tween's value on its beats.
+#[derive(Component, Debug)]
+pub struct TilePulse {
+    pub beat: Beat,
+    base: Color,
+}
+
+impl BeatBehavior for TilePulse {
+    type Target = &'static mut TileColor;
+
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
+
+    fn on_beat(&mut self, brightness: f32, _context: &BeatContext, mut color: BeatTarget<Self>) {
+        color.0 = self.base * brightness;
+    }
+
+    fn off_beat(&mut self, _context: &BeatContext, mut color: BeatTarget<Self>) {
+        color.0 = self.base;
+    }
+}
+
+/// Hurts the player standing on the tile when one of its beats starts, like
+/// spikes coming out of the floor.
+#[derive(Component, Deserialize, Debug, Clone)]
+pub struct TileHazard {
+    #[serde(default)]
+    pub beat: Beat,
+    pub damage: f32,
+}
+
+impl TileHazard {
+    fn system(
+        hazards: Query<(&TileHazard, &TilePos, &TilemapId)>,
+        tilemaps: Query<(&GlobalTransform, &TilemapGridSize)>,
+        character: Query<&Transform, With<Character>>,
+        song: Res<SongPlayback>,
+        mut hits: EventWriter<PlayerHit>,
+    ) {
+        if !song.bpm_timer.just_finished() {
+            return;
+        }
+        let Ok(character) = character.get_single() else {
+            return;
+        };
+        let position = character.translation.truncate();
+
+        // Standing across several spikes still only hurts once
+        let damage = hazards
+            .iter()
+            .filter(|(hazard, ..)| hazard.beat.is_active(song.beat_count))
+            .filter(|(_, tile, tilemap)| {
+                let Ok((transform, grid)) = tilemaps.get(tilemap.0) else {
+                    return false;
+                };
+                let center = transform.transform_point(Vec3::new(tile.x as f32 * grid.x, tile.y as f32 * grid.y, 0.0));
+                Rect::from_center_half_size(center.truncate(), Vec2::new(grid.x, grid.y) / 2.0).contains(position)
+            })
+            .map(|(hazard, ..)| hazard.damage)
+            .fold(0.0, f32::max);
+        if damage > 0.0 {
+            hits.send(PlayerHit { damage });
+        }
+    }
+}
+
+/// Terrain that is only solid on some beats: out of every `every` beats it is
+/// open for the first `open`. Gates open every few bars, barriers pop up for
+/// a beat or two.
+#[derive(Component, Debug)]
+pub struct BeatGate {
+    pub every: usize,
+    pub open: usize,
+    size: Vec2,
+}
+
+impl BeatGate {
+    fn bundle(every: usize, open: usize, size: Vec2) -> impl Bundle {
+        let gate = BeatGate { every, open, size };
+        (
+            Sprite { color: Color::DARK_GRAY, custom_size: Some(size), ..default() },
+            Handle::<Image>::default(),
+            RigidBody::Fixed,
+            Collider::cuboid(size.x / 2.0, size.y / 2.0),
+            CollisionGroups::new(collision::TERRAIN, Group::ALL),
+            gate.collider(),
+            gate,
+        )
+    }
+
+    pub fn is_open(&self, beat_count: usize) -> bool {
+        beat_count % self.every.max(1) < self.open
+    }
+
+    /// Lets monsters path around the gate while it is closed.
+    fn collider(&self) -> TerrainCollider {
+        TerrainCollider { blocks: Group::ALL, rects: vec![Rect::from_center_size(Vec2::ZERO, self.size)] }
+    }
+
+    fn system(
+        mut commands: Commands,
+        mut gates: Query<(Entity, &BeatGate, &mut Visibility, Has<ColliderDisabled>)>,
+        song: Res<SongPlayback>,
+    ) {
+        for (entity, gate, mut visibility, disabled) in gates.iter_mut() {
+            let open = gate.is_open(song.beat_count);
+            if open == disabled {
+                continue;
+            }
+            if open {
+                commands.entity(entity).insert(ColliderDisabled).remove::<TerrainCollider>();
+                *visibility = Visibility::Hidden;
+            } else {
+                commands.entity(entity).remove::<ColliderDisabled>().insert(gate.collider());
+                *visibility = Visibility::Inherited;
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::{prelude::*, reflect::GetPath};
+use serde::{Deserialize, Serialize};
+
+use crate::{beat::{Beat, BeatSet}, song::SongPlayback};
+
+pub struct BeatTweenPlugin;
+impl Plugin for BeatTweenPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_type::<BeatTween>()
+            .register_type::<TweenMode>()
+            .add_systems(Update, BeatTween::system.in_set(BeatSet::Apply));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Default, Serialize, Deserialize)]
+pub enum TweenMode {
+    /// Replace the field with the tween's value
+    #[default]
+    Set,
+    /// Add the tween's value to the field's resting value
+    Add,
+    /// Multiply the field's resting value by the tween's value
+    Multiply,
+}
+
+/// Animates any `f32` reachable through reflection on one of the entity's
+/// components, e.g. `"TextureAtlasSprite"` / `"color.alpha"` for a fade or
+/// `"Transform"` / `"translation.y"` for a bob.
+///
+/// `component` is the type path (full or short) of a registered component and
+/// `field` a reflect path into it. Off the beat the field goes back to the
+/// value it had before the tween first touched it.
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatTween {
+    pub beat: Beat,
+    pub component: String,
+    pub field: String,
+    #[serde(default)]
+    pub mode: TweenMode,
+    #[reflect(ignore)]
+    #[serde(skip)]
+    rest: Option<f32>,
+}
+
+impl BeatTween {
+    pub fn new(beat: Beat, component: impl Into<String>, field: impl Into<String>, mode: TweenMode) -> Self {
+        Self { beat, component: component.into(), field: field.into(), mode, rest: None }
+    }
+
+    fn system(world: &mut World) {
+        let mut tweens = world.query::<(Entity, &BeatTween)>();
+        let song = world.resource::<SongPlayback>();
+        let values: Vec<_> = tweens
+            .iter(world)
+            .map(|(entity, tween)| (entity, tween.clone(), tween.beat.sample(song)))
+            .collect();
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+
+        for (entity, tween, value) in values {
+            let reflect_component = registry.get_with_type_path(&tween.component)
+                .or_else(|| registry.get_with_short_type_path(&tween.component))
+                .and_then(|registration| registration.data::<ReflectComponent>());
+            let Some(reflect_component) = reflect_component else {
+                warn!("BeatTween on {:?}: {} is not a registered component", entity, tween.component);
+                world.entity_mut(entity).remove::<BeatTween>();
+                continue;
+            };
+
+            let mut entity_mut = world.entity_mut(entity);
+            let Some(mut component) = reflect_component.reflect_mut(&mut entity_mut) else {
+                continue;
+            };
+            let field = match component.path_mut::<f32>(tween.field.as_str()) {
+                Ok(field) => field,
+                Err(err) => {
+                    warn!("BeatTween on {:?}: {}", entity, err);
+                    entity_mut.remove::<BeatTween>();
+                    continue;
+                }
+            };
+
+            let rest = tween.rest.unwrap_or(*field);
+            *field = match (value, tween.mode) {
+                (None, _) => rest,
+                (Some(value), TweenMode::Set) => value,
+                (Some(value), TweenMode::Add) => rest + value,
+                (Some(value), TweenMode::Multiply) => rest * value,
+            };
+            if tween.rest.is_none() {
+                if let Some(mut tween) = entity_mut.get_mut::<BeatTween>() {
+                    tween.rest = Some(rest);
+                }
+            }
+        }
+    }
+}
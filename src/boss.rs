@@ -0,0 +1,124 @@
+use bevy::{prelude::*, ecs::system::EntityCommands};
+
+use crate::{combat::Health, song::SongPlayback};
+
+pub struct BossPlugin;
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_systems(Update, (Boss::advance, BossHealthBar::spawn, BossHealthBar::update));
+    }
+}
+
+pub type PhaseFn = Box<dyn Fn(&mut EntityCommands) + Send + Sync>;
+
+pub enum PhaseTrigger {
+    /// Health has dropped below this fraction of the maximum
+    HealthBelow(f32),
+    /// The song has reached the marker with this name
+    Marker(String),
+}
+
+impl PhaseTrigger {
+    fn fired(&self, health: &Health, song: &SongPlayback) -> bool {
+        match self {
+            PhaseTrigger::HealthBelow(fraction) => health.fraction() < *fraction,
+            PhaseTrigger::Marker(name) => song.reached(name),
+        }
+    }
+}
+
+/// One stage of a boss fight. `enter` inserts the phase's behaviors and
+/// attacks, `exit` removes them again once any of the `until` triggers fire.
+/// The last phase lasts until the boss dies.
+pub struct BossPhase {
+    pub until: Vec<PhaseTrigger>,
+    pub enter: PhaseFn,
+    pub exit: PhaseFn,
+}
+
+#[derive(Component)]
+pub struct Boss {
+    phases: Vec<BossPhase>,
+    current: Option<usize>,
+}
+
+impl Boss {
+    pub fn new(phases: Vec<BossPhase>) -> Self {
+        Self { phases, current: None }
+    }
+
+    fn advance(
+        mut commands: Commands,
+        mut bosses: Query<(Entity, &mut Boss, &Health)>,
+        song: Res<SongPlayback>,
+    ) {
+        for (entity, mut boss, health) in bosses.iter_mut() {
+            let next = match boss.current {
+                None => 0,
+                Some(current) if current + 1 < boss.phases.len()
+                    && boss.phases[current].until.iter().any(|t| t.fired(health, &song)) => current + 1,
+                _ => continue,
+            };
+
+            let mut entity_commands = commands.entity(entity);
+            if let Some(current) = boss.current {
+                (boss.phases[current].exit)(&mut entity_commands);
+            }
+            (boss.phases[next].enter)(&mut entity_commands);
+            boss.current = Some(next);
+        }
+    }
+}
+
+#[derive(Component)]
+struct BossHealthBar {
+    boss: Entity,
+}
+
+impl BossHealthBar {
+    fn spawn(
+        mut commands: Commands,
+        bosses: Query<Entity, Added<Boss>>,
+    ) {
+        for boss in bosses.iter() {
+            commands.spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(16.0),
+                    left: Val::Percent(20.0),
+                    width: Val::Percent(60.0),
+                    height: Val::Px(12.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            }).with_children(|builder| {
+                builder.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: Color::CRIMSON.into(),
+                        ..default()
+                    },
+                    BossHealthBar { boss },
+                ));
+            });
+        }
+    }
+
+    fn update(
+        mut commands: Commands,
+        mut bars: Query<(&BossHealthBar, &Parent, &mut Style)>,
+        bosses: Query<&Health, With<Boss>>,
+    ) {
+        for (bar, parent, mut style) in bars.iter_mut() {
+            match bosses.get(bar.boss) {
+                Ok(health) => style.width = Val::Percent(health.fraction() * 100.0),
+                Err(_) => commands.entity(parent.get()).despawn_recursive(),
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::SimpleAnimation,
+    beat::{Beat, BeatContext},
+    character::{Character, PlayerHit},
+    collision,
+    level::LevelScoped,
+    monster::Spawning,
+    song::SongPlayback,
+    status::Statuses,
+    telegraph::{Telegraphed, TelegraphedPlugin},
+    terrain::TerrainCollider,
+};
+
+pub struct BulletPlugin;
+impl Plugin for BulletPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_type::<BeatShooter>()
+            .register_type::<BulletPattern>()
+            .add_plugins(TelegraphedPlugin::<BeatShooter>::default())
+            .add_systems(Startup, load_bullet_assets)
+            .add_systems(Update, (BeatShooter::system, hit_player, Lifetime::system));
+    }
+}
+
+#[derive(Resource)]
+struct BulletAssets {
+    texture_atlas: Handle<TextureAtlas>,
+}
+
+fn load_bullet_assets(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let texture_handle = asset_server.load(r"sprites/effect-bullet-impact-explosion/Purple Effect Bullet Impact Explosion 32x32.png");
+    let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(32.0, 32.0), 20, 16, None, None);
+    commands.insert_resource(BulletAssets { texture_atlas: texture_atlases.add(texture_atlas) });
+}
+
+#[derive(Clone, Copy, Debug, Reflect, Serialize, Deserialize)]
+#[reflect(Default, Serialize, Deserialize)]
+pub enum BulletPattern {
+    /// `count` bullets fanned out over `spread` radians around the player
+    Aimed { count: usize, spread: f32 },
+    /// `count` bullets evenly spaced in a full circle
+    Ring { count: usize },
+    /// Like a ring of `arms`, but rotated by `step` radians after every volley
+    Spiral { arms: usize, step: f32 },
+}
+
+impl Default for BulletPattern {
+    fn default() -> Self {
+        BulletPattern::Aimed { count: 1, spread: 0.0 }
+    }
+}
+
+/// Fires a volley of `pattern` on each of `subdivisions` evenly spaced points
+/// within the active beats, at least one. The beat's tween gives the bullet
+/// speed.
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatShooter {
+    pub beat: Beat,
+    pub pattern: BulletPattern,
+    pub subdivisions: usize,
+    #[reflect(ignore)]
+    #[serde(skip)]
+    last_volley: Option<(usize, usize)>,
+    #[reflect(ignore)]
+    #[serde(skip)]
+    angle: f32,
+}
+
+impl BeatShooter {
+    pub fn new(beat: Beat, pattern: BulletPattern, subdivisions: usize) -> Self {
+        Self { beat, pattern, subdivisions, last_volley: None, angle: 0.0 }
+    }
+
+    fn directions(&mut self, aim: Vec2) -> Vec<Vec2> {
+        match self.pattern {
+            BulletPattern::Aimed { count, spread } => {
+                let base = aim.y.atan2(aim.x);
+                (0..count).map(|i| {
+                    let offset = if count > 1 { spread * (i as f32 / (count - 1) as f32 - 0.5) } else { 0.0 };
+                    Vec2::from_angle(base + offset)
+                }).collect()
+            }
+            BulletPattern::Ring { count } =>
+                (0..count).map(|i| Vec2::from_angle(i as f32 * 2.0 * PI / count as f32)).collect(),
+            BulletPattern::Spiral { arms, step } => {
+                let start = self.angle;
+                self.angle = (self.angle + step) % (2.0 * PI);
+                (0..arms).map(|i| Vec2::from_angle(start + i as f32 * 2.0 * PI / arms as f32)).collect()
+            }
+        }
+    }
+
+    fn system(
+        mut commands: Commands,
+        mut shooters: Query<(&GlobalTransform, &mut BeatShooter, Option<&Statuses>), Without<Spawning>>,
+        character: Query<&Transform, With<Character>>,
+        song: Res<SongPlayback>,
+        assets: Res<BulletAssets>,
+    ) {
+        let character = character.single().translation.truncate();
+        let p = song.bpm_timer.percent();
+        for (transform, mut shooter, statuses) in shooters.iter_mut() {
+            if statuses.is_some_and(Statuses::suppresses_actions) {
+                continue;
+            }
+            let Some(speed) = shooter.beat.sample(&song) else {
+                continue;
+            };
+            // Authored data and the default can leave this at zero
+            let subdivisions = shooter.subdivisions.max(1);
+            let subdivision = ((p * subdivisions as f32) as usize).min(subdivisions - 1);
+            let volley = Some((song.beat_count, subdivision));
+            if shooter.last_volley == volley {
+                continue;
+            }
+            shooter.last_volley = volley;
+
+            let origin = transform.translation().truncate();
+            for direction in shooter.directions(character - origin) {
+                spawn_bullet(&mut commands, &assets, origin, direction * speed);
+            }
+        }
+    }
+}
+
+impl Telegraphed for BeatShooter {
+    fn preview(&mut self, beat: usize, position: Vec2, context: &BeatContext) -> Option<Vec2> {
+        if !self.beat.is_active(beat) {
+            return None;
+        }
+        match self.pattern {
+            BulletPattern::Aimed { .. } => Some((context.character.truncate() - position).normalize_or_zero()),
+            BulletPattern::Ring { .. } | BulletPattern::Spiral { .. } => Some(Vec2::ZERO),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct EnemyBullet {
+    pub damage: f32,
+}
+
+fn spawn_bullet(commands: &mut Commands, assets: &BulletAssets, origin: Vec2, linvel: Vec2) {
+    commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: assets.texture_atlas.clone(),
+            sprite: TextureAtlasSprite::new(111),
+            transform: Transform {
+                translation: origin.extend(2.0),
+                rotation: Quat::from_rotation_z(linvel.y.atan2(linvel.x) + PI),
+                ..default()
+            },
+            ..default()
+        },
+        SimpleAnimation {
+            range: 111..115,
+            timer: Timer::from_seconds(0.1, TimerMode::Repeating),
+        },
+        RigidBody::KinematicVelocityBased,
+        Velocity::linear(linvel),
+        Collider::ball(6.0),
+        Sensor,
+        CollisionGroups::new(collision::MONSTER_PROJECTILE, collision::PLAYER | collision::TERRAIN),
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC | ActiveCollisionTypes::KINEMATIC_FIXED,
+        ActiveEvents::COLLISION_EVENTS,
+        EnemyBullet { damage: 1.0 },
+        Lifetime(Timer::from_seconds(4.0, TimerMode::Once)),
+        LevelScoped,
+    ));
+}
+
+fn hit_player(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    mut hits: EventWriter<PlayerHit>,
+    bullets: Query<&EnemyBullet>,
+    character: Query<(), With<Character>>,
+    terrain: Query<(), With<TerrainCollider>>,
+) {
+    for collision in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = *collision else {
+            continue;
+        };
+        for (bullet, other) in [(a, b), (b, a)] {
+            let Ok(hit) = bullets.get(bullet) else {
+                continue;
+            };
+            if character.contains(other) {
+                hits.send(PlayerHit { damage: hit.damage });
+                commands.entity(bullet).despawn();
+            } else if terrain.contains(other) {
+                commands.entity(bullet).despawn();
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Lifetime(pub Timer);
+
+impl Lifetime {
+    fn system(
+        mut commands: Commands,
+        mut query: Query<(Entity, &mut Lifetime)>,
+        time: Res<Time>,
+    ) {
+        for (entity, mut lifetime) in query.iter_mut() {
+            if lifetime.0.tick(time.delta()).just_finished() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::{input::mouse::MouseWheel, prelude::*, transform::TransformSystem};
+use bevy_rapier2d::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    beat::{Beat, BeatSet},
+    character::{Character, PlayerHit},
+    props::Exploded,
+    song::{SongPlayback, BEATS_PER_BAR},
+    terrain::{LevelBounds, Unload},
+    tween::{Curve, Ease, Tween, TweenType},
+};
+
+pub struct CameraPlugin;
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .init_resource::<CameraEffects>()
+            .add_systems(Startup, start_camera)
+            .add_systems(Update, (FollowCamera::zoom, CameraEffects::toggle))
+            .add_systems(Update, (CameraJuice::shake, CameraJuice::beat, CameraFlash::system).chain().in_set(BeatSet::Apply))
+            // After the character controller has moved the player
+            .add_systems(PostUpdate, FollowCamera::follow.after(PhysicsSet::Writeback).before(TransformSystem::TransformPropagate));
+    }
+}
+
+/// The camera cuts straight to a character further than this from its
+/// focus, like one put at a new level's start.
+const CUT_DISTANCE: f32 = 512.0;
+
+/// How much trauma a big explosion right on top of the camera adds, falling
+/// off to none at three times its radius.
+const EXPLOSION_TRAUMA: f32 = 0.6;
+const HIT_TRAUMA: f32 = 0.4;
+
+fn start_camera(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), FollowCamera::default(), CameraJuice::default()));
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: Color::NONE.into(),
+            ..default()
+        },
+        CameraFlash,
+    ));
+}
+
+/// Keeps the character in view. The camera stays put while the character
+/// moves around within `deadzone` of its focus, then eases after it without
+/// showing past the edges of the loaded levels.
+#[derive(Component, Debug)]
+pub struct FollowCamera {
+    /// Half the size of the box the character moves freely in
+    pub deadzone: Vec2,
+    /// How quickly the camera catches up, per second
+    pub smoothing: f32,
+    /// Screen pixels per world pixel. Whole numbers keep the tiles crisp.
+    pub zoom: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Where the camera looks, before snapping to screen pixels
+    focus: Vec2,
+}
+
+impl Default for FollowCamera {
+    fn default() -> Self {
+        Self { deadzone: Vec2::new(48.0, 32.0), smoothing: 6.0, zoom: 1.0, min_zoom: 1.0, max_zoom: 4.0, focus: Vec2::ZERO }
+    }
+}
+
+impl FollowCamera {
+    /// Zooms a step per notch of the mouse wheel.
+    fn zoom(
+        mut wheel: EventReader<MouseWheel>,
+        mut cameras: Query<&mut FollowCamera>,
+    ) {
+        let steps: f32 = wheel.read().map(|event| event.y.signum()).sum();
+        for mut camera in cameras.iter_mut() {
+            camera.zoom = (camera.zoom + steps).clamp(camera.min_zoom, camera.max_zoom);
+        }
+    }
+
+    fn follow(
+        mut cameras: Query<(&mut FollowCamera, &mut Transform, &mut OrthographicProjection, Option<&CameraJuice>), Without<Character>>,
+        character: Query<&Transform, With<Character>>,
+        levels: Query<&LevelBounds, Without<Unload>>,
+        time: Res<Time>,
+    ) {
+        let Ok(character) = character.get_single() else {
+            return;
+        };
+        let target = character.translation.truncate();
+        let bounds = levels.iter().map(|bounds| bounds.0).reduce(|a, b| a.union(b));
+
+        for (mut camera, mut transform, mut projection, juice) in cameras.iter_mut() {
+            let offset = target - camera.focus;
+            let wanted = target - offset.clamp(-camera.deadzone, camera.deadzone);
+            if wanted.distance(camera.focus) > CUT_DISTANCE {
+                camera.focus = wanted;
+            } else {
+                let t = 1.0 - (-camera.smoothing * time.delta_seconds()).exp();
+                camera.focus = camera.focus.lerp(wanted, t);
+            }
+            if let Some(bounds) = bounds {
+                camera.focus = clamp_view(camera.focus, projection.area.half_size(), bounds);
+            }
+
+            // Moving by less than a screen pixel makes the tiles shimmer.
+            // Screen pixels are counted at the punched zoom, which is what
+            // the projection shows
+            let pixels = camera.zoom * juice.map_or(1.0, |juice| juice.punch_value);
+            let shake = juice.map_or(Vec2::ZERO, |juice| juice.offset);
+            let snapped = ((camera.focus + shake) * pixels).round() / pixels;
+            transform.translation = snapped.extend(transform.translation.z);
+
+            let scale = 1.0 / pixels;
+            if projection.scale != scale {
+                projection.scale = scale;
+            }
+        }
+    }
+}
+
+/// How strong the camera effects are, for players who find them too much.
+/// F1 goes through the settings.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraEffects {
+    #[default]
+    Full,
+    Reduced,
+    Off,
+}
+
+impl CameraEffects {
+    pub fn intensity(self) -> f32 {
+        match self {
+            CameraEffects::Full => 1.0,
+            CameraEffects::Reduced => 0.35,
+            CameraEffects::Off => 0.0,
+        }
+    }
+
+    fn toggle(keyboard_input: Res<Input<KeyCode>>, mut effects: ResMut<CameraEffects>) {
+        if keyboard_input.just_pressed(KeyCode::F1) {
+            *effects = match *effects {
+                CameraEffects::Full => CameraEffects::Reduced,
+                CameraEffects::Reduced => CameraEffects::Off,
+                CameraEffects::Off => CameraEffects::Full,
+            };
+            info!("Camera effects: {:?}", *effects);
+        }
+    }
+}
+
+/// Effects on top of [`FollowCamera`]: a zoom punch on the beats of `punch`,
+/// a flash on the beats of `flash` and shake from hits and explosions, all
+/// scaled by [`CameraEffects`].
+#[derive(Component, Debug)]
+pub struct CameraJuice {
+    /// Zoom multiplier over the beat
+    pub punch: Beat,
+    /// Opacity of the flash over the beat
+    pub flash: Beat,
+    /// Largest shake offset, in world pixels
+    pub max_shake: f32,
+    /// Trauma lost per second
+    pub recovery: f32,
+    /// Shake builds up from this, going from 0 to 1
+    trauma: f32,
+    offset: Vec2,
+    punch_value: f32,
+}
+
+impl Default for CameraJuice {
+    fn default() -> Self {
+        Self {
+            // The downbeat of every bar
+            punch: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 1.04, b: 1.0, end: 0.5, ease: Ease::Out(Curve::Quad), ..default() }, freq: BEATS_PER_BAR, on_beat: 0 },
+            flash: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 0.2, b: 0.0, end: 0.4, ease: Ease::Out(Curve::Quad), ..default() }, freq: BEATS_PER_BAR, on_beat: 0 },
+            max_shake: 12.0,
+            recovery: 1.5,
+            trauma: 0.0,
+            offset: Vec2::ZERO,
+            punch_value: 1.0,
+        }
+    }
+}
+
+impl CameraJuice {
+    fn shake(
+        mut cameras: Query<(&mut CameraJuice, &GlobalTransform)>,
+        mut hits: EventReader<PlayerHit>,
+        mut explosions: EventReader<Exploded>,
+        effects: Res<CameraEffects>,
+        time: Res<Time>,
+    ) {
+        let hit = hits.read().count() > 0;
+        let explosions: Vec<&Exploded> = explosions.read().collect();
+        let mut rng = thread_rng();
+        for (mut juice, transform) in cameras.iter_mut() {
+            let position = transform.translation().truncate();
+            let mut trauma = juice.trauma - juice.recovery * time.delta_seconds();
+            if hit {
+                trauma += HIT_TRAUMA;
+            }
+            for explosion in &explosions {
+                let falloff = 1.0 - explosion.position.distance(position) / (explosion.radius * 3.0);
+                trauma += EXPLOSION_TRAUMA * falloff.max(0.0);
+            }
+            juice.trauma = trauma.clamp(0.0, 1.0);
+
+            // Squared so small knocks barely register and big ones rattle
+            let amount = juice.max_shake * juice.trauma * juice.trauma * effects.intensity();
+            juice.offset = Vec2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0)) * amount;
+        }
+    }
+
+    fn beat(mut cameras: Query<&mut CameraJuice>, song: Res<SongPlayback>, effects: Res<CameraEffects>) {
+        for mut juice in cameras.iter_mut() {
+            let punch = juice.punch.sample(&song).unwrap_or(1.0);
+            juice.punch_value = 1.0 + (punch - 1.0) * effects.intensity();
+        }
+    }
+}
+
+/// A full screen overlay that flashes on the beats of the camera's
+/// [`CameraJuice::flash`], changing color from bar to bar.
+#[derive(Component, Debug)]
+pub struct CameraFlash;
+
+impl CameraFlash {
+    fn system(
+        mut flashes: Query<&mut BackgroundColor, With<CameraFlash>>,
+        cameras: Query<&CameraJuice>,
+        song: Res<SongPlayback>,
+        effects: Res<CameraEffects>,
+    ) {
+        let Ok(juice) = cameras.get_single() else {
+            return;
+        };
+        let alpha = juice.flash.sample(&song).unwrap_or(0.0) * effects.intensity();
+        let hue = (song.beat_count / BEATS_PER_BAR * 67 % 360) as f32;
+        for mut color in flashes.iter_mut() {
+            color.0 = Color::hsla(hue, 1.0, 0.6, alpha);
+        }
+    }
+}
+
+/// Moves `center` so a view `half_size` around it stays inside `bounds`,
+/// centering on bounds too small to fill the view.
+fn clamp_view(center: Vec2, half_size: Vec2, bounds: Rect) -> Vec2 {
+    let min = bounds.min + half_size;
+    let max = bounds.max - half_size;
+    let clamp = |center: f32, min: f32, max: f32, middle: f32| if min <= max { center.clamp(min, max) } else { middle };
+    Vec2::new(
+        clamp(center.x, min.x, max.x, bounds.center().x),
+        clamp(center.y, min.y, max.y, bounds.center().y),
+    )
+}
@@ -1,14 +1,19 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{prelude::*, transform::TransformSystem, utils::HashMap};
 use bevy_rapier2d::prelude::*;
 use enum_map::enum_map;
 
-use crate::{animation::{SimpleAnimation, SimpleWalkingAnimation, Direction}, song::SongPlayback};
+use crate::{animation::{SimpleAnimation, SimpleWalkingAnimation, Direction}, collision, combat::{Health, PlayerAttack}, song::SongPlayback, status::{Inflicts, StatusKind}, terrain::{LevelSpawned, RegisterLdtkEntity}};
 
 pub struct CharacterPlugin;
 impl Plugin for CharacterPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
+            .add_event::<PlayerHit>()
+            .register_ldtk_entity("PlayerStart", |commands, _| {
+                commands.insert(PlayerStart);
+            })
             .add_systems(Startup, (init_asset_hack, apply_deferred, load_character).chain())
+            .add_systems(PostUpdate, PlayerStart::system.before(TransformSystem::TransformPropagate))
             .add_systems(FixedUpdate, (move_character, energy_ball_attack, move_urb));
     }
 }
@@ -52,6 +57,44 @@ fn init_asset_hack(
 #[derive(Component)]
 pub struct Character;
 
+/// Where the player is put when a [`PlacePlayer`] level spawns. Levels
+/// without one put the player in their middle.
+#[derive(Component)]
+pub struct PlayerStart;
+
+/// Marks a level the player should be moved into once it spawns, unlike
+/// streamed chunks or neighbouring levels that come in around the player.
+#[derive(Component)]
+pub struct PlacePlayer;
+
+impl PlayerStart {
+    fn system(
+        mut spawned: EventReader<LevelSpawned>,
+        placed: Query<(), With<PlacePlayer>>,
+        starts: Query<(&Transform, &Parent), (With<PlayerStart>, Without<Character>)>,
+        mut character: Query<&mut Transform, With<Character>>,
+    ) {
+        for event in spawned.read() {
+            if !placed.contains(event.entity) {
+                continue;
+            }
+            let start = starts.iter().find(|(_, parent)| parent.get() == event.entity);
+            let position = match start {
+                Some((transform, _)) => event.bounds.min + transform.translation.truncate(),
+                None => event.bounds.center(),
+            };
+            for mut transform in character.iter_mut() {
+                transform.translation = position.extend(transform.translation.z);
+            }
+        }
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct PlayerHit {
+    pub damage: f32,
+}
+
 fn load_character(
     assets: Res<AssetHack>,
     mut commands: Commands,
@@ -85,7 +128,14 @@ fn load_character(
         RigidBody::KinematicPositionBased,
         GravityScale(0.0),
         Collider::ball(5.0),
+        CollisionGroups::new(collision::PLAYER, Group::ALL),
+        // Only walls stop the player, monsters hurt instead
+        KinematicCharacterController {
+            filter_groups: Some(CollisionGroups::new(collision::PLAYER, collision::TERRAIN)),
+            ..default()
+        },
         Character,
+        Health::new(10.0),
         Velocity::default(),
         EnergyBallAttack,
     ));
@@ -93,7 +143,7 @@ fn load_character(
 
 fn move_character(
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<&mut Transform, With<Character>>,
+    mut query: Query<&mut KinematicCharacterController, With<Character>>,
 ) {
     let mut direction = Vec3::default();
     if keyboard_input.pressed(KeyCode::A) {
@@ -110,8 +160,10 @@ fn move_character(
     }
     if let Some(mut direction) = direction.try_normalize() {
         direction *= 4.0;
-        for mut transform in &mut query {
-            transform.translation += direction;
+        for mut controller in &mut query {
+            // Rapier moves the character once a frame, which can take in
+            // several fixed steps
+            controller.translation = Some(controller.translation.unwrap_or_default() + direction.truncate());
         }
     }
 }
@@ -119,6 +171,14 @@ fn move_character(
 #[derive(Debug, Component)]
 struct EnergyBallAttack;
 
+/// What the energy ball inflicts, going round with the beats of the bar.
+const ATTACK_STATUSES: [Inflicts; 4] = [
+    Inflicts { kind: StatusKind::Freeze, beats: 1 },
+    Inflicts { kind: StatusKind::Slow, beats: 2 },
+    Inflicts { kind: StatusKind::Burn, beats: 4 },
+    Inflicts { kind: StatusKind::Stun, beats: 1 },
+];
+
 fn energy_ball_attack(
     mut commands: Commands,
     query: Query<(&Transform, &SimpleWalkingAnimation), With<EnergyBallAttack>>,
@@ -148,6 +208,9 @@ fn energy_ball_attack(
             },
             Collider::ball(16.0),
             Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            PlayerAttack { damage: 1.0 },
+            ATTACK_STATUSES[song.beat_count % ATTACK_STATUSES.len()].clone(),
             Velocity {
                 linvel: dir.to_vec() * 8.,
                 ..default()
@@ -166,4 +229,26 @@ fn move_urb(
     for (mut transform, velocity) in &mut query {
         transform.translation += velocity.linvel.extend(0.);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn fixed_steps_in_one_frame_add_up() {
+        let mut world = World::new();
+        let mut keyboard_input = Input::<KeyCode>::default();
+        keyboard_input.press(KeyCode::D);
+        world.insert_resource(keyboard_input);
+        let character = world.spawn((Character, KinematicCharacterController::default())).id();
+
+        world.run_system_once(move_character);
+        world.run_system_once(move_character);
+
+        let controller = world.get::<KinematicCharacterController>(character).unwrap();
+        assert_eq!(controller.translation, Some(Vec2::new(8.0, 0.0)));
+    }
+}
@@ -0,0 +1,140 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::prelude::*;
+
+use crate::{
+    character::Character,
+    city::CityGenerator,
+    ldtk::LdtkMap,
+    terrain::{LdtkLevel, LevelSource, Unload},
+};
+
+pub struct ChunkPlugin;
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_systems(Startup, load_chunks)
+            .add_systems(Update, TerrainChunks::stream.run_if(resource_exists::<TerrainChunks>()));
+    }
+}
+
+/// Chunks already loaded stay until they are this much further away than
+/// [`TerrainChunks::margin`], so walking along a border doesn't churn them.
+const UNLOAD_SLACK: f32 = 256.0;
+
+/// Terrain streamed in around the player as a grid of chunks, drawn with the
+/// tilesets of `map`. The origin is the bottom left corner of chunk (0, 0),
+/// which for cities puts the player on a crossing.
+#[derive(Resource, Debug)]
+pub struct TerrainChunks {
+    pub map: Handle<LdtkMap>,
+    pub source: ChunkSource,
+    /// Seeds each chunk, so the same seed lays out the same world
+    pub seed: u64,
+    /// How far around the player chunks are loaded
+    pub margin: f32,
+    loaded: HashMap<IVec2, Entity>,
+}
+
+#[derive(Debug)]
+pub enum ChunkSource {
+    /// Levels of the map, one picked for each chunk. They all take the size
+    /// of the first.
+    Templates(Vec<String>),
+    /// A city generated for each chunk
+    City(CityGenerator),
+}
+
+/// A level spawned by [`TerrainChunks`], at its chunk coordinate.
+#[derive(Component, Debug)]
+pub struct Chunk(pub IVec2);
+
+fn load_chunks(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(TerrainChunks::new(asset_server.load("maps/test.ldtk"), ChunkSource::City(CityGenerator::default()), random()));
+}
+
+impl TerrainChunks {
+    pub fn new(map: Handle<LdtkMap>, source: ChunkSource, seed: u64) -> Self {
+        Self { map, source, seed, margin: 1200.0, loaded: HashMap::default() }
+    }
+
+    fn chunk_seed(&self, chunk: IVec2) -> u64 {
+        self.seed ^ ((chunk.x as u32 as u64) << 32 | chunk.y as u32 as u64)
+    }
+
+    fn size(&self, map: &LdtkMap) -> Option<Vec2> {
+        match &self.source {
+            ChunkSource::Templates(templates) => {
+                let level = map.level(templates.first()?)?;
+                Some(Vec2::new(level.px_wid as f32, level.px_hei as f32))
+            }
+            ChunkSource::City(generator) => Some(generator.size().as_vec2()),
+        }
+    }
+
+    fn level(&self, chunk: IVec2) -> LevelSource {
+        let seed = self.chunk_seed(chunk);
+        match &self.source {
+            ChunkSource::Templates(templates) => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                LevelSource::Map(templates[rng.gen_range(0..templates.len())].clone())
+            }
+            ChunkSource::City(generator) => LevelSource::Generated(Box::new(generator.generate(seed))),
+        }
+    }
+
+    /// The chunks overlapping `area`.
+    fn covering(area: Rect, size: Vec2) -> impl Iterator<Item = IVec2> {
+        let min = (area.min / size).floor().as_ivec2();
+        let max = (area.max / size).floor().as_ivec2();
+        (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+    }
+
+    /// Unloads every chunk, for when something else takes over the terrain.
+    pub fn unload_all(&mut self, commands: &mut Commands) {
+        for (_, entity) in self.loaded.drain() {
+            commands.entity(entity).insert(Unload);
+        }
+    }
+
+    pub fn stream(
+        mut commands: Commands,
+        mut chunks: ResMut<TerrainChunks>,
+        maps: Res<Assets<LdtkMap>>,
+        character: Query<&Transform, With<Character>>,
+    ) {
+        let Some(map) = maps.get(&chunks.map) else {
+            return;
+        };
+        let Some(size) = chunks.size(map) else {
+            return;
+        };
+        let Ok(character) = character.get_single() else {
+            return;
+        };
+        let position = character.translation.truncate();
+
+        let keep = Rect::from_center_half_size(position, Vec2::splat(chunks.margin + UNLOAD_SLACK));
+        let keep: Vec<IVec2> = Self::covering(keep, size).collect();
+        chunks.loaded.retain(|chunk, &mut entity| {
+            let kept = keep.contains(chunk);
+            if !kept {
+                commands.entity(entity).insert(Unload);
+            }
+            kept
+        });
+
+        let load = Rect::from_center_half_size(position, Vec2::splat(chunks.margin));
+        for chunk in Self::covering(load, size) {
+            if chunks.loaded.contains_key(&chunk) {
+                continue;
+            }
+            let level = LdtkLevel {
+                map: chunks.map.clone(),
+                source: chunks.level(chunk),
+                origin: Some(chunk.as_vec2() * size),
+            };
+            let entity = commands.spawn((SpatialBundle::default(), level, Chunk(chunk))).id();
+            chunks.loaded.insert(chunk, entity);
+        }
+    }
+}
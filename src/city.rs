@@ -0,0 +1,546 @@
+use std::{collections::VecDeque, ops::Range};
+
+use bevy::math::UVec2;
+use rand::prelude::*;
+
+use crate::ldtk::{LayerInstance, Level, TileInstance};
+
+/// Tile size of the modern city tilesets.
+const GRID: u32 = 16;
+
+/// Road width in cells. The roads along a city's edges are half as wide, so
+/// neighbouring cities join into full roads.
+const ROAD: u32 = 4;
+
+/// Size range of the blocks between roads, in cells.
+const BLOCK: Range<u32> = 10..19;
+
+/// Smallest width and height of a city: a block between the half roads on
+/// its edges.
+pub const MIN_SIZE: u32 = ROAD + BLOCK.start;
+
+/// Shortest lot that fits two buildings with an alley between.
+const MIN_SPLIT: usize = 8;
+
+const ASPHALT: u32 = 66;
+/// Zebra stripes for crossing a horizontal road, and a vertical one
+const CROSSWALK: [u32; 2] = [64, 65];
+const PAVING: Autotile = Autotile::block(3);
+const GRASS: Autotile = Autotile::block(7);
+const ROOFS: [Autotile; 4] = [Autotile::block(64), Autotile::block(68), Autotile::block(72), Autotile::block(76)];
+
+/// Rows of the fence styles, each with a left end, middle and right end for
+/// horizontal runs and then a tile for vertical ones.
+const FENCE_STYLES: [u32; 8] = [0, 2, 4, 6, 8, 10, 12, 14];
+
+/// Vehicles seen from the side, for parking along horizontal roads
+const SIDE_VEHICLES: [Stamp; 3] = [Stamp::new(0, 0, 3, 2), Stamp::new(3, 0, 3, 2), Stamp::new(6, 0, 3, 2)];
+/// Vehicles seen from the front or back, for vertical roads and parking lots
+const FRONT_VEHICLES: [Stamp; 6] = [
+    Stamp::new(0, 2, 2, 2), Stamp::new(3, 2, 2, 2), Stamp::new(6, 2, 2, 2),
+    Stamp::new(0, 4, 2, 2), Stamp::new(3, 4, 2, 2), Stamp::new(6, 4, 2, 2),
+];
+
+/// Identifiers of the generated layers that
+/// [`TileCollision`](crate::terrain::TileCollision) has rules for.
+pub const VEHICLES_LAYER: &str = "Vehicles";
+pub const FENCES_LAYER: &str = "Fences";
+pub const BUILDINGS_LAYER: &str = "Buildings";
+
+/// Tileset uids the generated layers use, those of `maps/test.ldtk` by
+/// default.
+#[derive(Debug, Clone)]
+pub struct CityTilesets {
+    pub street: i64,
+    pub buildings: i64,
+    pub fences: i64,
+    pub vehicles: i64,
+}
+
+impl Default for CityTilesets {
+    fn default() -> Self {
+        Self { street: 3, buildings: 1, fences: 2, vehicles: 4 }
+    }
+}
+
+/// Lays out city blocks of streets, buildings, parks and parked vehicles.
+/// The same seed always gives the same city, and every walkable cell can be
+/// reached from every other.
+#[derive(Debug, Clone)]
+pub struct CityGenerator {
+    /// Size in cells
+    pub width: u32,
+    pub height: u32,
+    pub tilesets: CityTilesets,
+}
+
+impl Default for CityGenerator {
+    fn default() -> Self {
+        Self { width: 64, height: 64, tilesets: CityTilesets::default() }
+    }
+}
+
+impl CityGenerator {
+    /// Size in pixels.
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.width, self.height) * GRID
+    }
+
+    /// A level with "Vehicles", "Fences", "Buildings" and "Ground" layers,
+    /// topmost first. Panics if the generator is smaller than [`MIN_SIZE`]
+    /// either way.
+    pub fn generate(&self, seed: u64) -> Level {
+        self.layout(seed).into_level(&self.tilesets)
+    }
+
+    fn layout(&self, seed: u64) -> City {
+        assert!(
+            self.width >= MIN_SIZE && self.height >= MIN_SIZE,
+            "City of {}x{} is smaller than {MIN_SIZE}x{MIN_SIZE}", self.width, self.height,
+        );
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut city = City::new(self.width, self.height);
+        let columns = roads(&mut rng, self.width);
+        let rows = roads(&mut rng, self.height);
+
+        for road in &columns[1..columns.len() - 1] {
+            for row in &rows[1..rows.len() - 1] {
+                city.crosswalks(road, row);
+            }
+        }
+        for x in columns.windows(2).map(|pair| pair[0].end..pair[1].start) {
+            for y in rows.windows(2).map(|pair| pair[0].end..pair[1].start) {
+                city.block(&mut rng, x.clone(), y);
+            }
+        }
+        for road in &columns[1..columns.len() - 1] {
+            city.park_along(&mut rng, road.clone(), &rows, false);
+        }
+        for road in &rows[1..rows.len() - 1] {
+            city.park_along(&mut rng, road.clone(), &columns, true);
+        }
+        city
+    }
+}
+
+/// The roads across `size` cells, including the half roads on both edges.
+fn roads(rng: &mut StdRng, size: u32) -> Vec<Range<u32>> {
+    let mut roads = vec![0..ROAD / 2];
+    let end = size - ROAD / 2;
+    let mut position = ROAD / 2;
+    loop {
+        let block = rng.gen_range(BLOCK);
+        if position + block + ROAD + BLOCK.start > end {
+            break;
+        }
+        position += block;
+        roads.push(position..position + ROAD);
+        position += ROAD;
+    }
+    roads.push(end..size);
+    roads
+}
+
+/// Picks one of a 4x4 block of tiles by which neighbours are in the same
+/// area: a 3x3 area, a column one wide and a row one high.
+#[derive(Debug, Clone, Copy)]
+struct Autotile {
+    first: u32,
+}
+
+impl Autotile {
+    /// The block with its top left tile at `first`, the column right of the
+    /// 3x3 area and the row below it.
+    const fn block(first: u32) -> Self {
+        Self { first }
+    }
+
+    fn pick(self, up: bool, down: bool, left: bool, right: bool) -> u32 {
+        let index = |before: bool, after: bool| match (before, after) {
+            (false, true) => Some(0),
+            (true, true) => Some(1),
+            (true, false) => Some(2),
+            (false, false) => None,
+        };
+        let (x, y) = match (index(left, right), index(up, down)) {
+            (Some(x), Some(y)) => (x, y),
+            (None, Some(y)) => (3, y),
+            (Some(x), None) => (x, 3),
+            (None, None) => (3, 3),
+        };
+        self.first + y * 16 + x
+    }
+}
+
+/// The cells of the rectangle with the tiles `autotile` picks for them.
+fn autotiled(x: Range<u32>, y: Range<u32>, autotile: Autotile) -> impl Iterator<Item = (u32, u32, u32)> {
+    y.clone().flat_map(move |cy| {
+        let (x, y) = (x.clone(), y.clone());
+        x.clone().map(move |cx| (cx, cy, autotile.pick(cy > y.start, cy + 1 < y.end, cx > x.start, cx + 1 < x.end)))
+    })
+}
+
+/// A rectangle of tiles in the vehicles tileset.
+#[derive(Debug, Clone, Copy)]
+struct Stamp {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Stamp {
+    const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+}
+
+/// Cells of the city being laid out, y down like LDtk.
+#[derive(Debug, Clone, PartialEq)]
+struct City {
+    width: u32,
+    height: u32,
+    ground: Vec<u32>,
+    buildings: Vec<Option<u32>>,
+    fences: Vec<Option<u32>>,
+    vehicles: Vec<Option<u32>>,
+    blocked: Vec<bool>,
+}
+
+impl City {
+    fn new(width: u32, height: u32) -> Self {
+        let cells = (width * height) as usize;
+        Self {
+            width,
+            height,
+            ground: vec![ASPHALT; cells],
+            buildings: vec![None; cells],
+            fences: vec![None; cells],
+            vehicles: vec![None; cells],
+            blocked: vec![false; cells],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn fill(&mut self, x: Range<u32>, y: Range<u32>, tile: u32) {
+        for y in y {
+            for x in x.clone() {
+                let i = self.index(x, y);
+                self.ground[i] = tile;
+            }
+        }
+    }
+
+    /// Stripes on the roads leading into the crossing of `column` and `row`.
+    fn crosswalks(&mut self, column: &Range<u32>, row: &Range<u32>) {
+        for x in [column.start - 1, column.end] {
+            for y in row.clone() {
+                let i = self.index(x, y);
+                self.ground[i] = CROSSWALK[0];
+            }
+        }
+        for y in [row.start - 1, row.end] {
+            for x in column.clone() {
+                let i = self.index(x, y);
+                self.ground[i] = CROSSWALK[1];
+            }
+        }
+    }
+
+    /// A paved block with buildings, a fenced park or a parking lot on it,
+    /// leaving the sidewalk around it clear.
+    fn block(&mut self, rng: &mut StdRng, x: Range<u32>, y: Range<u32>) {
+        for (cx, cy, tile) in autotiled(x.clone(), y.clone(), PAVING) {
+            let i = self.index(cx, cy);
+            self.ground[i] = tile;
+        }
+        let (x, y) = (x.start + 1..x.end - 1, y.start + 1..y.end - 1);
+        match rng.gen_range(0..10) {
+            0..=5 => self.buildings(rng, x, y),
+            6..=7 => self.park(rng, x, y),
+            _ => self.parking_lot(rng, x, y),
+        }
+    }
+
+    /// One building on the lot, or two split along its long side with an
+    /// alley between.
+    fn buildings(&mut self, rng: &mut StdRng, x: Range<u32>, y: Range<u32>) {
+        let lots = if rng.gen_bool(0.5) || x.len().max(y.len()) < MIN_SPLIT {
+            vec![(x, y)]
+        } else if x.len() >= y.len() {
+            let split = rng.gen_range(x.start + 3..x.end - 4);
+            vec![(x.start..split, y.clone()), (split + 1..x.end, y)]
+        } else {
+            let split = rng.gen_range(y.start + 3..y.end - 4);
+            vec![(x.clone(), y.start..split), (x, split + 1..y.end)]
+        };
+        for (x, y) in lots {
+            let cells: Vec<(u32, u32)> = y.clone().flat_map(|cy| x.clone().map(move |cx| (cx, cy))).collect();
+            if self.try_block(&cells) {
+                for (cx, cy, tile) in autotiled(x, y, *ROOFS.choose(rng).unwrap()) {
+                    let i = self.index(cx, cy);
+                    self.buildings[i] = Some(tile);
+                }
+            }
+        }
+    }
+
+    /// Grass fenced in along the lot's edge, with a gate in the middle of
+    /// each side.
+    fn park(&mut self, rng: &mut StdRng, x: Range<u32>, y: Range<u32>) {
+        for (cx, cy, tile) in autotiled(x.clone(), y.clone(), GRASS) {
+            let i = self.index(cx, cy);
+            self.ground[i] = tile;
+        }
+        let style = FENCE_STYLES.choose(rng).unwrap() * 16;
+        let gate = |range: &Range<u32>, at: u32| {
+            let middle = range.start + range.len() as u32 / 2;
+            at + 1 >= middle && at <= middle
+        };
+        for horizontal in [y.start, y.end - 1] {
+            let run: Vec<(u32, u32)> = x.clone().filter(|&cx| !gate(&x, cx)).map(|cx| (cx, horizontal)).collect();
+            if self.try_block(&run) {
+                for (cx, cy) in run {
+                    let end_left = cx == x.start || gate(&x, cx - 1);
+                    let end_right = cx + 1 == x.end || gate(&x, cx + 1);
+                    let tile = match (end_left, end_right) {
+                        (true, _) => style,
+                        (_, true) => style + 2,
+                        _ => style + 1,
+                    };
+                    let i = self.index(cx, cy);
+                    self.fences[i] = Some(tile);
+                }
+            }
+        }
+        for vertical in [x.start, x.end - 1] {
+            let run: Vec<(u32, u32)> =
+                (y.start + 1..y.end - 1).filter(|&cy| !gate(&y, cy)).map(|cy| (vertical, cy)).collect();
+            if self.try_block(&run) {
+                for (cx, cy) in run {
+                    let i = self.index(cx, cy);
+                    self.fences[i] = Some(style + 3);
+                }
+            }
+        }
+    }
+
+    /// Asphalt with a row of vehicles parked along its top.
+    fn parking_lot(&mut self, rng: &mut StdRng, x: Range<u32>, y: Range<u32>) {
+        self.fill(x.clone(), y.clone(), ASPHALT);
+        let mut cx = x.start;
+        while cx + 2 <= x.end {
+            if rng.gen_bool(0.7) {
+                self.try_vehicle(*FRONT_VEHICLES.choose(rng).unwrap(), cx, y.start);
+            }
+            cx += 3;
+        }
+    }
+
+    /// Vehicles parked in the lane along one side of `road`, clear of the
+    /// crossings with `across`.
+    fn park_along(&mut self, rng: &mut StdRng, road: Range<u32>, across: &[Range<u32>], horizontal: bool) {
+        let lane = if rng.gen_bool(0.5) { road.start } else { road.end - 2 };
+        for segment in across.windows(2).map(|pair| pair[0].end + 2..pair[1].start - 2) {
+            let mut at = segment.start;
+            while at < segment.end {
+                let stamp = if horizontal { *SIDE_VEHICLES.choose(rng).unwrap() } else { *FRONT_VEHICLES.choose(rng).unwrap() };
+                let length = if horizontal { stamp.width } else { stamp.height };
+                if at + length > segment.end {
+                    break;
+                }
+                if rng.gen_bool(0.3) {
+                    let (x, y) = if horizontal { (at, lane) } else { (lane, at) };
+                    if self.try_vehicle(stamp, x, y) {
+                        at += length;
+                    }
+                }
+                at += 1;
+            }
+        }
+    }
+
+    fn try_vehicle(&mut self, stamp: Stamp, x: u32, y: u32) -> bool {
+        let cells: Vec<(u32, u32)> = (0..stamp.height).flat_map(|dy| (0..stamp.width).map(move |dx| (x + dx, y + dy))).collect();
+        if cells.iter().any(|&(cx, cy)| cx >= self.width || cy >= self.height) || !self.try_block(&cells) {
+            return false;
+        }
+        for dy in 0..stamp.height {
+            for dx in 0..stamp.width {
+                let i = self.index(x + dx, y + dy);
+                self.vehicles[i] = Some((stamp.y + dy) * 16 + stamp.x + dx);
+            }
+        }
+        true
+    }
+
+    /// Blocks `cells`, unless they are already taken or would cut part of
+    /// the city off.
+    fn try_block(&mut self, cells: &[(u32, u32)]) -> bool {
+        let indices: Vec<usize> = cells.iter().map(|&(x, y)| self.index(x, y)).collect();
+        if indices.iter().any(|&i| self.blocked[i]) {
+            return false;
+        }
+        for &i in &indices {
+            self.blocked[i] = true;
+        }
+        if self.is_connected() {
+            return true;
+        }
+        for &i in &indices {
+            self.blocked[i] = false;
+        }
+        false
+    }
+
+    /// Whether every walkable cell can be reached from every other.
+    fn is_connected(&self) -> bool {
+        let Some(start) = self.blocked.iter().position(|blocked| !blocked) else {
+            return true;
+        };
+        let mut seen = vec![false; self.blocked.len()];
+        seen[start] = true;
+        let mut queue = VecDeque::from([start]);
+        let mut reached = 1;
+        let width = self.width as usize;
+        while let Some(i) = queue.pop_front() {
+            let (x, y) = (i % width, i / width);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (i + width < self.blocked.len()).then(|| i + width),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if !seen[next] && !self.blocked[next] {
+                    seen[next] = true;
+                    reached += 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached == self.blocked.iter().filter(|blocked| !**blocked).count()
+    }
+
+    fn into_level(self, tilesets: &CityTilesets) -> Level {
+        let ground: Vec<Option<u32>> = self.ground.iter().copied().map(Some).collect();
+        let layers = vec![
+            self.layer(VEHICLES_LAYER, tilesets.vehicles, &self.vehicles),
+            self.layer(FENCES_LAYER, tilesets.fences, &self.fences),
+            self.layer(BUILDINGS_LAYER, tilesets.buildings, &self.buildings),
+            self.layer("Ground", tilesets.street, &ground),
+        ];
+        Level {
+            identifier: "City".into(),
+            iid: String::new(),
+            world_x: 0,
+            world_y: 0,
+            px_wid: (self.width * GRID) as i32,
+            px_hei: (self.height * GRID) as i32,
+            layer_instances: Some(layers),
+            neighbours: Vec::new(),
+            field_instances: Vec::new(),
+        }
+    }
+
+    fn layer(&self, identifier: &str, tileset: i64, tiles: &[Option<u32>]) -> LayerInstance {
+        let grid_tiles = tiles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, tile)| {
+                let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+                tile.map(|t| TileInstance { px: [(x * GRID) as i32, (y * GRID) as i32], t, f: 0, a: 1.0 })
+            })
+            .collect();
+        LayerInstance {
+            identifier: identifier.into(),
+            layer_type: "Tiles".into(),
+            c_wid: self.width,
+            c_hei: self.height,
+            grid_size: GRID,
+            opacity: 1.0,
+            tileset_def_uid: Some(tileset),
+            px_offset_x: 0,
+            px_offset_y: 0,
+            visible: true,
+            grid_tiles,
+            auto_layer_tiles: Vec::new(),
+            int_grid_csv: Vec::new(),
+            entity_instances: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: Range<u64> = 0..32;
+
+    #[test]
+    fn same_seed_same_city() {
+        let generator = CityGenerator::default();
+        for seed in SEEDS {
+            assert_eq!(generator.layout(seed), generator.layout(seed), "seed {seed}");
+        }
+        assert_ne!(generator.layout(1), generator.layout(2));
+    }
+
+    #[test]
+    fn every_walkable_cell_is_reachable() {
+        let generator = CityGenerator::default();
+        for seed in SEEDS {
+            let city = generator.layout(seed);
+            assert!(city.is_connected(), "seed {seed}");
+            assert!(city.blocked.iter().any(|blocked| *blocked), "seed {seed} has nothing in it");
+        }
+    }
+
+    /// Neighbouring cities only join up if nothing stands on their edges.
+    #[test]
+    fn edges_are_clear() {
+        let generator = CityGenerator { width: 48, height: 80, ..Default::default() };
+        for seed in SEEDS {
+            let city = generator.layout(seed);
+            for x in 0..city.width {
+                for y in [0, city.height - 1] {
+                    assert!(!city.blocked[city.index(x, y)], "seed {seed} blocks {x}, {y}");
+                }
+            }
+            for y in 0..city.height {
+                for x in [0, city.width - 1] {
+                    assert!(!city.blocked[city.index(x, y)], "seed {seed} blocks {x}, {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn smallest_city_generates() {
+        let generator = CityGenerator { width: MIN_SIZE, height: MIN_SIZE, ..Default::default() };
+        for seed in SEEDS {
+            assert!(generator.layout(seed).is_connected(), "seed {seed}");
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_small_a_city_panics() {
+        CityGenerator { width: MIN_SIZE - 1, height: 64, ..Default::default() }.generate(0);
+    }
+
+    #[test]
+    fn level_has_a_tile_for_every_ground_cell() {
+        let generator = CityGenerator::default();
+        let level = generator.generate(7);
+        let layers = level.layer_instances.unwrap();
+        assert_eq!(layers.iter().map(|layer| layer.identifier.as_str()).collect::<Vec<_>>(), ["Vehicles", "Fences", "Buildings", "Ground"]);
+        assert_eq!(layers[3].grid_tiles.len(), (generator.width * generator.height) as usize);
+        for tile in &layers[1].grid_tiles {
+            assert!(FENCE_STYLES.iter().any(|style| (style * 16..style * 16 + 4).contains(&tile.t)));
+        }
+    }
+}
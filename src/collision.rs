@@ -0,0 +1,9 @@
+use bevy_rapier2d::prelude::Group;
+
+pub const PLAYER: Group = Group::GROUP_1;
+pub const MONSTER_PROJECTILE: Group = Group::GROUP_2;
+pub const MONSTER: Group = Group::GROUP_3;
+/// Colliders generated from the map, see [`TileCollision`](crate::terrain::TileCollision)
+pub const TERRAIN: Group = Group::GROUP_4;
+/// Sensors the player walks into, like pickups and song triggers
+pub const TRIGGER: Group = Group::GROUP_5;
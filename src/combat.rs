@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    character::{Character, PlayerHit},
+    elite::ShieldUp,
+};
+
+pub struct CombatPlugin;
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_event::<DamageEvent>()
+            .add_event::<Killed>()
+            .init_resource::<Score>()
+            .configure_sets(Update, (CombatSet::Damage, CombatSet::Despawn).chain())
+            .add_systems(Update, (player_attack_hits, player_hits, apply_damage, collect_rewards).chain().in_set(CombatSet::Damage));
+    }
+}
+
+/// Systems reacting to [`Killed`] go between these two sets, while the dead
+/// entity is still around. [`Despawn`](CombatSet::Despawn) is where the
+/// death pipeline removes it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CombatSet {
+    Damage,
+    Despawn,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.current / self.max
+    }
+}
+
+/// Ignores all damage while present.
+#[derive(Component, Debug)]
+pub struct Invulnerable;
+
+/// Points the player scores for killing this entity.
+#[derive(Component, Debug, Clone)]
+pub struct Reward {
+    pub points: u32,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Score(pub u32);
+
+#[derive(Event, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// The attack that dealt the damage, if any
+    pub source: Option<Entity>,
+}
+
+/// Sent once, on the frame an entity's health runs out.
+#[derive(Event, Debug)]
+pub struct Killed {
+    pub entity: Entity,
+}
+
+#[derive(Component, Debug)]
+pub struct PlayerAttack {
+    pub damage: f32,
+}
+
+fn player_attack_hits(
+    mut collisions: EventReader<CollisionEvent>,
+    mut damage: EventWriter<DamageEvent>,
+    attacks: Query<&PlayerAttack>,
+    targets: Query<(), With<Health>>,
+    parents: Query<&Parent>,
+) {
+    for collision in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = *collision else {
+            continue;
+        };
+        for (attack_entity, other) in [(a, b), (b, a)] {
+            let Ok(attack) = attacks.get(attack_entity) else {
+                continue;
+            };
+            // Extra colliders on a monster live on its children
+            let target = if targets.contains(other) {
+                Some(other)
+            } else {
+                parents.get(other).ok().map(|p| p.get()).filter(|p| targets.contains(*p))
+            };
+            if let Some(target) = target {
+                damage.send(DamageEvent { target, amount: attack.damage, source: Some(attack_entity) });
+            }
+        }
+    }
+}
+
+fn player_hits(
+    mut hits: EventReader<PlayerHit>,
+    mut damage: EventWriter<DamageEvent>,
+    character: Query<Entity, With<Character>>,
+) {
+    let character = character.single();
+    for hit in hits.read() {
+        damage.send(DamageEvent { target: character, amount: hit.damage, source: None });
+    }
+}
+
+fn apply_damage(
+    mut damage: EventReader<DamageEvent>,
+    mut killed: EventWriter<Killed>,
+    mut health: Query<&mut Health, (Without<Invulnerable>, Without<ShieldUp>)>,
+) {
+    for event in damage.read() {
+        let Ok(mut health) = health.get_mut(event.target) else {
+            continue;
+        };
+        if health.current <= 0.0 {
+            continue;
+        }
+        health.current = (health.current - event.amount).max(0.0);
+        if health.current <= 0.0 {
+            killed.send(Killed { entity: event.target });
+        }
+    }
+}
+
+fn collect_rewards(
+    mut killed: EventReader<Killed>,
+    rewards: Query<&Reward>,
+    mut score: ResMut<Score>,
+) {
+    for event in killed.read() {
+        if let Ok(reward) = rewards.get(event.entity) {
+            score.0 += reward.points;
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{f32::consts::FRAC_PI_2, ops::Range};
+
+use bevy::prelude::*;
+
+use crate::{
+    character::Character,
+    combat::{CombatSet, Killed},
+    level::LevelScoped,
+    song::SongPlayback,
+};
+
+pub struct DeathPlugin;
+impl Plugin for DeathPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_event::<Died>()
+            .add_systems(Startup, load_death_assets)
+            .add_systems(Update, die.in_set(CombatSet::Despawn))
+            .add_systems(Update, (Explosion::system, Decal::system));
+    }
+}
+
+/// The burst in the middle column of the explosion sheets.
+const EXPLOSION_FRAMES: Range<usize> = 186..190;
+
+#[derive(Resource)]
+struct DeathAssets {
+    explosion: Handle<TextureAtlas>,
+}
+
+fn load_death_assets(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let texture_handle = asset_server.load(r"sprites/effect-bullet-impact-explosion/Yellow Effect Bullet Impact Explosion 32x32.png");
+    let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(32.0, 32.0), 20, 16, None, None);
+    commands.insert_resource(DeathAssets { explosion: texture_atlases.add(texture_atlas) });
+}
+
+/// Sent for every entity the death pipeline removes, with where it died.
+#[derive(Event, Debug)]
+pub struct Died {
+    pub entity: Entity,
+    pub position: Vec2,
+}
+
+/// Leaves the entity's sprite behind as a fading decal for `beats` beats.
+#[derive(Component, Debug, Clone)]
+pub struct Corpse {
+    pub beats: usize,
+}
+
+/// Played where the entity dies.
+#[derive(Component, Debug, Clone)]
+pub struct DeathSound(pub Handle<AudioSource>);
+
+/// Replaces killed entities, player aside, with an explosion and maybe a
+/// corpse, then despawns them along with their children.
+fn die(
+    mut commands: Commands,
+    mut killed: EventReader<Killed>,
+    mut died: EventWriter<Died>,
+    dying: Query<(&GlobalTransform, Option<&Children>, Option<&Corpse>, Option<&DeathSound>), Without<Character>>,
+    sprites: Query<(&TextureAtlasSprite, &Handle<TextureAtlas>, &GlobalTransform)>,
+    assets: Res<DeathAssets>,
+    song: Res<SongPlayback>,
+) {
+    for event in killed.read() {
+        let Ok((transform, children, corpse, sound)) = dying.get(event.entity) else {
+            continue;
+        };
+        let position = transform.translation().truncate();
+
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: assets.explosion.clone(),
+                sprite: TextureAtlasSprite::new(EXPLOSION_FRAMES.start),
+                transform: Transform::from_translation(position.extend(2.0)).with_scale(Vec3::splat(2.0)),
+                ..default()
+            },
+            Explosion::new(&song),
+            LevelScoped,
+        ));
+
+        let sprite = children.and_then(|children| children.iter().find_map(|&child| sprites.get(child).ok()));
+        if let (Some(corpse), Some((sprite, texture_atlas, sprite_transform))) = (corpse, sprite) {
+            commands.spawn((
+                SpriteSheetBundle {
+                    texture_atlas: texture_atlas.clone(),
+                    sprite: TextureAtlasSprite { index: sprite.index, color: Decal::COLOR, ..default() },
+                    transform: Transform {
+                        translation: position.extend(0.2),
+                        rotation: Quat::from_rotation_z(FRAC_PI_2),
+                        scale: sprite_transform.compute_transform().scale,
+                    },
+                    ..default()
+                },
+                Decal { until: song.beat_count + corpse.beats, beats: corpse.beats },
+                LevelScoped,
+            ));
+        }
+
+        if let Some(sound) = sound {
+            commands.spawn(AudioBundle { source: sound.0.clone(), settings: PlaybackSettings::DESPAWN });
+        }
+
+        died.send(Died { entity: event.entity, position });
+        commands.entity(event.entity).despawn_recursive();
+    }
+}
+
+/// Plays through [`EXPLOSION_FRAMES`] so the last frame lands on a beat: the
+/// next one, or the one after if the next is less than half a beat away.
+#[derive(Component, Debug)]
+struct Explosion {
+    start: f32,
+    end: f32,
+}
+
+impl Explosion {
+    fn new(song: &SongPlayback) -> Self {
+        let percent = song.bpm_timer.percent();
+        let beats = if percent > 0.5 { 2.0 } else { 1.0 };
+        Self { start: song.beat_count as f32 + percent, end: song.beat_count as f32 + beats }
+    }
+
+    fn system(
+        mut commands: Commands,
+        mut explosions: Query<(Entity, &Explosion, &mut TextureAtlasSprite)>,
+        song: Res<SongPlayback>,
+    ) {
+        let now = song.beat_count as f32 + song.bpm_timer.percent();
+        for (entity, explosion, mut sprite) in explosions.iter_mut() {
+            let progress = (now - explosion.start) / (explosion.end - explosion.start);
+            if progress >= 1.0 {
+                commands.entity(entity).despawn();
+                continue;
+            }
+            sprite.index = EXPLOSION_FRAMES.start + (progress.max(0.0) * EXPLOSION_FRAMES.len() as f32) as usize;
+        }
+    }
+}
+
+/// Fades out over its last `beats` beats, gone on beat `until`.
+#[derive(Component, Debug)]
+struct Decal {
+    until: usize,
+    beats: usize,
+}
+
+impl Decal {
+    const COLOR: Color = Color::rgba(0.3, 0.3, 0.3, 0.8);
+
+    fn system(
+        mut commands: Commands,
+        mut decals: Query<(Entity, &Decal, &mut TextureAtlasSprite)>,
+        song: Res<SongPlayback>,
+    ) {
+        for (entity, decal, mut sprite) in decals.iter_mut() {
+            if song.beat_count >= decal.until {
+                commands.entity(entity).despawn();
+                continue;
+            }
+            let remaining = (decal.until - song.beat_count) as f32 - song.bpm_timer.percent();
+            sprite.color.set_a(Self::COLOR.a() * remaining / decal.beats.max(1) as f32);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use bevy::{prelude::*, ecs::query::Has, reflect::{ReflectMut, Struct}};
+use rand::prelude::*;
+
+use crate::{
+    beat::{Beat, BeatSet},
+    combat::{Health, Reward},
+    monster::Spawning,
+    song::SongPlayback,
+    steering::SpeedScale,
+};
+
+pub struct ElitePlugin;
+impl Plugin for ElitePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_systems(Update, (Elite::apply, Elite::double_time).chain().in_set(BeatSet::Prepare))
+            // After telegraphs have set sprite colors
+            .add_systems(PostUpdate, Shielded::system);
+    }
+}
+
+/// Chance of a spawned monster being an elite.
+const ELITE_CHANCE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    /// Moves faster
+    Fast,
+    /// Takes more hits to kill
+    Armored,
+    /// Breaks into more monsters of its kind on death
+    Splits,
+    /// Acts twice as often
+    DoubleTime,
+    /// Can only be hurt on even beats
+    Shielded,
+}
+
+impl Modifier {
+    const ALL: [Modifier; 5] = [Modifier::Fast, Modifier::Armored, Modifier::Splits, Modifier::DoubleTime, Modifier::Shielded];
+
+    fn tint(self) -> Color {
+        match self {
+            Modifier::Fast => Color::rgb(1.0, 1.0, 0.4),
+            Modifier::Armored => Color::rgb(0.6, 0.6, 0.7),
+            Modifier::Splits => Color::rgb(0.5, 1.0, 0.5),
+            Modifier::DoubleTime => Color::rgb(1.0, 0.5, 1.0),
+            Modifier::Shielded => Color::rgb(0.5, 0.8, 1.0),
+        }
+    }
+
+    fn reward(self) -> f32 {
+        match self {
+            Modifier::Fast | Modifier::Splits => 1.5,
+            Modifier::Armored | Modifier::Shielded => 2.0,
+            Modifier::DoubleTime => 2.5,
+        }
+    }
+}
+
+/// The color a monster's sprites return to after flashing.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Tint(pub Color);
+
+/// A monster variant with modifiers on top of its archetype, applied the frame
+/// after it spawns.
+#[derive(Component, Debug, Clone)]
+pub struct Elite {
+    pub modifiers: Vec<Modifier>,
+}
+
+impl Elite {
+    /// Rolls whether a new monster is an elite, and with which one or two
+    /// modifiers.
+    pub fn roll(rng: &mut impl Rng) -> Option<Elite> {
+        if !rng.gen_bool(ELITE_CHANCE) {
+            return None;
+        }
+        let count = rng.gen_range(1..=2);
+        let modifiers = Modifier::ALL.choose_multiple(rng, count).copied().collect();
+        Some(Elite { modifiers })
+    }
+
+    pub fn has(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(&modifier)
+    }
+
+    fn tint(&self) -> Color {
+        let sum = self.modifiers.iter().fold(Vec4::ZERO, |sum, m| sum + Vec4::from(m.tint().as_rgba_f32()));
+        Color::from(sum / self.modifiers.len().max(1) as f32)
+    }
+
+    fn apply(
+        mut commands: Commands,
+        mut elites: Query<(Entity, &Elite, Option<&Children>, Option<&mut Health>, Option<&mut Reward>), Added<Elite>>,
+        mut sprites: Query<&mut TextureAtlasSprite>,
+    ) {
+        for (entity, elite, children, health, reward) in elites.iter_mut() {
+            let tint = elite.tint();
+            commands.entity(entity).insert(Tint(tint));
+            if let Some(children) = children {
+                let mut iter = sprites.iter_many_mut(children.iter());
+                while let Some(mut sprite) = iter.fetch_next() {
+                    sprite.color = tint;
+                }
+            }
+
+            if elite.has(Modifier::Fast) {
+                commands.entity(entity).insert(SpeedScale(1.5));
+            }
+            if let (true, Some(mut health)) = (elite.has(Modifier::Armored), health) {
+                health.max *= 3.0;
+                health.current = health.max;
+            }
+            if elite.has(Modifier::Shielded) {
+                commands.entity(entity).insert(Shielded);
+            }
+            if let Some(mut reward) = reward {
+                let scale = elite.modifiers.iter().map(|m| m.reward()).product::<f32>();
+                reward.points = (reward.points as f32 * scale).round() as u32;
+            }
+        }
+    }
+
+    /// Halves the frequency of every [`Beat`] in the reflected components of
+    /// double-time elites and their children.
+    fn double_time(world: &mut World) {
+        let mut elites = world.query_filtered::<(Entity, &Elite, Option<&Children>), Added<Elite>>();
+        let entities: Vec<Entity> = elites.iter(world)
+            .filter(|(_, elite, _)| elite.has(Modifier::DoubleTime))
+            .flat_map(|(entity, _, children)| {
+                std::iter::once(entity).chain(children.map(|c| c.to_vec()).unwrap_or_default())
+            })
+            .collect();
+        if entities.is_empty() {
+            return;
+        }
+
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+        for entity in entities {
+            let type_ids: Vec<_> = world.entity(entity).archetype().components()
+                .filter_map(|id| world.components().get_info(id).and_then(|info| info.type_id()))
+                .collect();
+            let mut entity = world.entity_mut(entity);
+            for type_id in type_ids {
+                let Some(reflect_component) = registry.get_type_data::<ReflectComponent>(type_id) else {
+                    continue;
+                };
+                let Some(mut component) = reflect_component.reflect_mut(&mut entity) else {
+                    continue;
+                };
+                let ReflectMut::Struct(component) = component.reflect_mut() else {
+                    continue;
+                };
+                for i in 0..component.field_len() {
+                    if let Some(beat) = component.field_at_mut(i).and_then(|field| field.downcast_mut::<Beat>()) {
+                        beat.double_time();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How much of its sprites' opacity a shielded monster keeps while the
+/// shield is up.
+const SHIELD_ALPHA: f32 = 0.5;
+
+/// Can't be hurt, and is faded out, on odd beats.
+#[derive(Component, Debug)]
+pub struct Shielded;
+
+/// On a [`Shielded`] monster while its shield is up. Kept apart from
+/// [`Invulnerable`](crate::combat::Invulnerable) so the shield going down doesn't end invulnerability
+/// from anything else.
+#[derive(Component, Debug)]
+pub struct ShieldUp;
+
+impl Shielded {
+    fn system(
+        mut commands: Commands,
+        shielded: Query<(Entity, &Children, Has<ShieldUp>), (With<Shielded>, Without<Spawning>)>,
+        mut sprites: Query<&mut TextureAtlasSprite>,
+        song: Res<SongPlayback>,
+    ) {
+        let up = song.beat_count % 2 == 1;
+        for (entity, children, was_up) in shielded.iter() {
+            if up && !was_up {
+                commands.entity(entity).insert(ShieldUp);
+            } else if !up && was_up {
+                commands.entity(entity).remove::<ShieldUp>();
+            }
+
+            // Only the fade is ours: a sprite recolored since, like by a
+            // flash, has lost it already
+            let mut iter = sprites.iter_many_mut(children.iter());
+            while let Some(mut sprite) = iter.fetch_next() {
+                let faded = was_up && !sprite.is_changed();
+                let a = sprite.color.a();
+                if up && !faded {
+                    sprite.color.set_a(a * SHIELD_ALPHA);
+                } else if !up && faded {
+                    sprite.color.set_a(a / SHIELD_ALPHA);
+                }
+            }
+        }
+    }
+}
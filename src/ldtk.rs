@@ -0,0 +1,296 @@
+use std::fmt;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+
+/// An LDtk project, with the tileset images it uses.
+#[derive(Asset, TypePath, Debug)]
+pub struct LdtkMap {
+    pub project: LdtkJson,
+    /// Tileset images by tileset uid
+    pub tilesets: HashMap<i64, Handle<Image>>,
+}
+
+impl LdtkMap {
+    pub fn level(&self, identifier: &str) -> Option<&Level> {
+        self.project.levels.iter().find(|level| level.identifier == identifier)
+    }
+
+    pub fn level_by_iid(&self, iid: &str) -> Option<&Level> {
+        self.project.levels.iter().find(|level| level.iid == iid)
+    }
+
+    pub fn tileset(&self, uid: i64) -> Option<&TilesetDef> {
+        self.project.defs.tilesets.iter().find(|tileset| tileset.uid == uid)
+    }
+}
+
+/// The parts of the LDtk project format we use, see
+/// <https://ldtk.io/json/>.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LdtkJson {
+    pub defs: Definitions,
+    pub levels: Vec<Level>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Definitions {
+    pub tilesets: Vec<TilesetDef>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TilesetDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub rel_path: Option<String>,
+    pub tile_grid_size: u32,
+    pub spacing: u32,
+    pub padding: u32,
+    /// Strings attached to individual tiles in the tileset editor
+    #[serde(default)]
+    pub custom_data: Vec<TileCustomData>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TileCustomData {
+    pub tile_id: u32,
+    pub data: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Level {
+    pub identifier: String,
+    pub iid: String,
+    pub world_x: i32,
+    pub world_y: i32,
+    pub px_wid: i32,
+    pub px_hei: i32,
+    /// Topmost layer first. Missing when levels are saved in separate files.
+    pub layer_instances: Option<Vec<LayerInstance>>,
+    /// Levels touching this one in the world layout
+    #[serde(rename = "__neighbours", default)]
+    pub neighbours: Vec<Neighbour>,
+    #[serde(default)]
+    pub field_instances: Vec<FieldInstance>,
+}
+
+impl Level {
+    /// Where the level sits in Bevy's world, flipping LDtk's y axis.
+    pub fn bounds(&self) -> Rect {
+        let min = Vec2::new(self.world_x as f32, -(self.world_y + self.px_hei) as f32);
+        Rect::from_corners(min, min + Vec2::new(self.px_wid as f32, self.px_hei as f32))
+    }
+
+    /// The value of custom field `identifier`, unless it is missing or null.
+    pub fn field(&self, identifier: &str) -> Option<&serde_json::Value> {
+        field(&self.field_instances, identifier)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Neighbour {
+    pub level_iid: String,
+    /// Which side the neighbour is on: "n", "s", "w", "e", corners like "ne"
+    /// in GridVania, or "<" and ">" for levels on another world depth.
+    pub dir: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerInstance {
+    #[serde(rename = "__identifier")]
+    pub identifier: String,
+    #[serde(rename = "__type")]
+    pub layer_type: String,
+    #[serde(rename = "__cWid")]
+    pub c_wid: u32,
+    #[serde(rename = "__cHei")]
+    pub c_hei: u32,
+    #[serde(rename = "__gridSize")]
+    pub grid_size: u32,
+    #[serde(rename = "__opacity")]
+    pub opacity: f32,
+    #[serde(rename = "__tilesetDefUid")]
+    pub tileset_def_uid: Option<i64>,
+    pub px_offset_x: i32,
+    pub px_offset_y: i32,
+    pub visible: bool,
+    #[serde(default)]
+    pub grid_tiles: Vec<TileInstance>,
+    #[serde(default)]
+    pub auto_layer_tiles: Vec<TileInstance>,
+    /// IntGrid values row by row from the top left, 0 for empty cells
+    #[serde(default)]
+    pub int_grid_csv: Vec<i32>,
+    #[serde(default)]
+    pub entity_instances: Vec<EntityInstance>,
+}
+
+impl LayerInstance {
+    /// Cell of a tile, counted from the bottom left like Bevy's y axis.
+    pub fn tile_cell(&self, tile: &TileInstance) -> UVec2 {
+        let x = tile.px[0].max(0) as u32 / self.grid_size;
+        let y = tile.px[1].max(0) as u32 / self.grid_size;
+        UVec2::new(x, self.c_hei.saturating_sub(y + 1))
+    }
+
+    /// Non-empty IntGrid cells with their values, cells counted like
+    /// [`tile_cell`](Self::tile_cell).
+    pub fn int_grid_cells(&self) -> impl Iterator<Item = (UVec2, i32)> + '_ {
+        self.int_grid_csv.iter().enumerate().filter(|(_, &value)| value != 0).map(|(i, &value)| {
+            let (x, y) = (i as u32 % self.c_wid, i as u32 / self.c_wid);
+            (UVec2::new(x, self.c_hei.saturating_sub(y + 1)), value)
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TileInstance {
+    /// Top left corner of the tile within the layer, y down
+    pub px: [i32; 2],
+    /// Tile id within the tileset
+    pub t: u32,
+    /// Flip bits: 1 for x, 2 for y
+    pub f: u8,
+    #[serde(default = "opaque")]
+    pub a: f32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityInstance {
+    #[serde(rename = "__identifier")]
+    pub identifier: String,
+    pub iid: String,
+    /// Pivot point within the layer, y down
+    pub px: [i32; 2],
+    #[serde(rename = "__pivot")]
+    pub pivot: [f32; 2],
+    pub width: i32,
+    pub height: i32,
+    pub field_instances: Vec<FieldInstance>,
+}
+
+impl EntityInstance {
+    /// Center of the entity within its layer, from the bottom left like
+    /// Bevy's y axis.
+    pub fn center(&self, layer: &LayerInstance) -> Vec2 {
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        let top_left = Vec2::new(self.px[0] as f32, self.px[1] as f32) - Vec2::from(self.pivot) * size;
+        let center = top_left + size / 2.0;
+        Vec2::new(center.x, (layer.c_hei * layer.grid_size) as f32 - center.y)
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    /// The value of custom field `identifier`, unless it is missing or null.
+    pub fn field(&self, identifier: &str) -> Option<&serde_json::Value> {
+        field(&self.field_instances, identifier)
+    }
+}
+
+fn field<'a>(fields: &'a [FieldInstance], identifier: &str) -> Option<&'a serde_json::Value> {
+    fields
+        .iter()
+        .find(|field| field.identifier == identifier)
+        .map(|field| &field.value)
+        .filter(|value| !value.is_null())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FieldInstance {
+    #[serde(rename = "__identifier")]
+    pub identifier: String,
+    #[serde(rename = "__value")]
+    pub value: serde_json::Value,
+}
+
+fn opaque() -> f32 {
+    1.0
+}
+
+#[derive(Default)]
+pub struct LdtkLoader;
+
+#[derive(Debug)]
+pub enum LdtkLoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for LdtkLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LdtkLoadError::Io(err) => write!(f, "could not read LDtk file: {err}"),
+            LdtkLoadError::Json(err) => write!(f, "could not parse LDtk file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for LdtkLoadError {}
+
+impl From<std::io::Error> for LdtkLoadError {
+    fn from(err: std::io::Error) -> Self {
+        LdtkLoadError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for LdtkLoadError {
+    fn from(err: serde_json::Error) -> Self {
+        LdtkLoadError::Json(err)
+    }
+}
+
+impl AssetLoader for LdtkLoader {
+    type Asset = LdtkMap;
+    type Settings = ();
+    type Error = LdtkLoadError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<LdtkMap, LdtkLoadError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let project: LdtkJson = serde_json::from_slice(&bytes)?;
+
+            let mut tilesets = HashMap::default();
+            for tileset in &project.defs.tilesets {
+                let Some(rel_path) = &tileset.rel_path else {
+                    continue;
+                };
+                // The tilesets are drawn in Aseprite, with a png exported next to each
+                let rel_path = match rel_path.strip_suffix(".aseprite") {
+                    Some(stem) => format!("{stem}.png"),
+                    None => rel_path.clone(),
+                };
+                match load_context.asset_path().resolve_embed(&rel_path) {
+                    Ok(path) => {
+                        tilesets.insert(tileset.uid, load_context.load(path));
+                    }
+                    Err(err) => warn!("Bad path for tileset {}: {err}", tileset.identifier),
+                }
+            }
+            Ok(LdtkMap { project, tilesets })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ldtk"]
+    }
+}
@@ -0,0 +1,294 @@
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    character::{Character, PlacePlayer},
+    chunk::TerrainChunks,
+    collision,
+    ldtk::{LdtkMap, Level},
+    song::{SongEnded, SongPlayback},
+    terrain::{LdtkLevel, LevelSource, RegisterLdtkEntity, Unload},
+};
+
+pub struct LevelPlugin;
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_event::<LoadLevel>()
+            .register_ldtk_entity("Door", |commands, instance| {
+                let Some(level) = instance.field("level").and_then(|value| value.as_str()) else {
+                    warn!("Door {} has no level", instance.iid);
+                    return;
+                };
+                let half = instance.size() / 2.0;
+                commands.insert((
+                    Door { level: level.into() },
+                    Collider::cuboid(half.x, half.y),
+                    Sensor,
+                    CollisionGroups::new(collision::TRIGGER, collision::PLAYER),
+                    ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_FIXED,
+                    ActiveEvents::COLLISION_EVENTS,
+                ));
+            })
+            .add_systems(Startup, load_levels)
+            .add_systems(Update, (
+                Door::system,
+                LevelManager::follow_song,
+                LevelManager::load,
+                LevelManager::follow,
+            ).chain().after(TerrainChunks::stream));
+    }
+}
+
+/// Asks the [`LevelManager`] to replace whatever terrain is loaded with the
+/// level called `identifier`, putting the player at its start.
+#[derive(Event, Debug, Clone)]
+pub struct LoadLevel {
+    pub identifier: String,
+}
+
+/// Loads levels of `map` by name, along with their neighbours in the LDtk
+/// world layout so the player can walk from one into the next. Terrain is
+/// streamed in chunks until the first level is loaded.
+///
+/// Levels with a "marker" field are loaded once the song reaches that
+/// marker. When the song ends, the level named by the current level's "next"
+/// field is loaded.
+#[derive(Resource, Debug)]
+pub struct LevelManager {
+    pub map: Handle<LdtkMap>,
+    /// Iid of the level the player is in
+    current: Option<String>,
+    /// The current level and its neighbours, by iid
+    loaded: HashMap<String, Entity>,
+    /// Requested before the map finished loading
+    pending: Option<String>,
+}
+
+/// Gameplay entities spawned into the world rather than under a level, like
+/// monsters, bullets and decals, which go when another level is loaded.
+#[derive(Component, Debug)]
+pub struct LevelScoped;
+
+/// Takes the player to level `level` when walked into.
+#[derive(Component, Debug)]
+pub struct Door {
+    pub level: String,
+}
+
+fn load_levels(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(LevelManager::new(asset_server.load("maps/test.ldtk")));
+}
+
+impl LevelManager {
+    pub fn new(map: Handle<LdtkMap>) -> Self {
+        Self { map, current: None, loaded: HashMap::default(), pending: None }
+    }
+
+    /// The level the player is in, unless terrain is streamed.
+    pub fn current<'a>(&self, map: &'a LdtkMap) -> Option<&'a Level> {
+        map.level_by_iid(self.current.as_ref()?)
+    }
+
+    fn spawn(&mut self, commands: &mut Commands, level: &Level) -> Entity {
+        *self.loaded.entry(level.iid.clone()).or_insert_with(|| {
+            let source = LevelSource::Map(level.identifier.clone());
+            commands.spawn((SpatialBundle::default(), LdtkLevel { map: self.map.clone(), source, origin: None })).id()
+        })
+    }
+
+    /// Spawns the neighbours of the current level and unloads every other
+    /// level.
+    fn settle(&mut self, commands: &mut Commands, map: &LdtkMap) {
+        let Some(current) = self.current(map) else {
+            return;
+        };
+        let neighbours = current.neighbours
+            .iter()
+            // Levels on other depths overlap this one
+            .filter(|neighbour| neighbour.dir != "<" && neighbour.dir != ">")
+            .filter_map(|neighbour| map.level_by_iid(&neighbour.level_iid));
+        let keep: Vec<&Level> = std::iter::once(current).chain(neighbours).collect();
+
+        self.loaded.retain(|iid, &mut entity| {
+            let kept = keep.iter().any(|level| &level.iid == iid);
+            if !kept {
+                commands.entity(entity).insert(Unload);
+            }
+            kept
+        });
+        for level in keep {
+            self.spawn(commands, level);
+        }
+    }
+
+    fn load(
+        mut commands: Commands,
+        mut manager: ResMut<LevelManager>,
+        mut requests: EventReader<LoadLevel>,
+        chunks: Option<ResMut<TerrainChunks>>,
+        maps: Res<Assets<LdtkMap>>,
+        scoped: Query<Entity, With<LevelScoped>>,
+    ) {
+        if let Some(request) = requests.read().last() {
+            manager.pending = Some(request.identifier.clone());
+        }
+        let Some(map) = maps.get(&manager.map) else {
+            return;
+        };
+        let Some(identifier) = manager.pending.take() else {
+            return;
+        };
+        let Some(level) = map.level(&identifier) else {
+            warn!("No level {identifier} in LDtk map");
+            return;
+        };
+
+        if let Some(mut chunks) = chunks {
+            chunks.unload_all(&mut commands);
+            commands.remove_resource::<TerrainChunks>();
+        }
+        for (_, entity) in manager.loaded.drain() {
+            commands.entity(entity).insert(Unload);
+        }
+        for entity in scoped.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        let entity = manager.spawn(&mut commands, level);
+        commands.entity(entity).insert(PlacePlayer);
+        manager.current = Some(level.iid.clone());
+        manager.settle(&mut commands, map);
+    }
+
+    /// Makes the neighbour the player walked into the current level.
+    fn follow(
+        mut commands: Commands,
+        mut manager: ResMut<LevelManager>,
+        maps: Res<Assets<LdtkMap>>,
+        character: Query<&Transform, With<Character>>,
+    ) {
+        let Some(map) = maps.get(&manager.map) else {
+            return;
+        };
+        let (Some(current), Ok(character)) = (manager.current(map), character.get_single()) else {
+            return;
+        };
+        let position = character.translation.truncate();
+        if current.bounds().contains(position) {
+            return;
+        }
+        let entered = manager.loaded
+            .keys()
+            .filter_map(|iid| map.level_by_iid(iid))
+            .find(|level| level.bounds().contains(position));
+        if let Some(entered) = entered {
+            manager.current = Some(entered.iid.clone());
+            manager.settle(&mut commands, map);
+        }
+    }
+
+    fn follow_song(
+        manager: Res<LevelManager>,
+        maps: Res<Assets<LdtkMap>>,
+        song: Res<SongPlayback>,
+        mut ended: EventReader<SongEnded>,
+        mut requests: EventWriter<LoadLevel>,
+        mut reached: Local<HashSet<String>>,
+    ) {
+        let Some(map) = maps.get(&manager.map) else {
+            return;
+        };
+        for level in &map.project.levels {
+            let Some(marker) = level.field("marker").and_then(|value| value.as_str()) else {
+                continue;
+            };
+            if song.reached(marker) && reached.insert(level.iid.clone()) {
+                requests.send(LoadLevel { identifier: level.identifier.clone() });
+            }
+        }
+
+        if ended.read().count() > 0 {
+            let next = manager.current(map).and_then(|level| level.field("next")).and_then(|value| value.as_str());
+            if let Some(next) = next {
+                requests.send(LoadLevel { identifier: next.into() });
+            }
+        }
+    }
+}
+
+impl Door {
+    fn system(
+        mut collisions: EventReader<CollisionEvent>,
+        doors: Query<&Door>,
+        character: Query<(), With<Character>>,
+        mut requests: EventWriter<LoadLevel>,
+    ) {
+        for collision in collisions.read() {
+            let CollisionEvent::Started(a, b, _) = *collision else {
+                continue;
+            };
+            for (door, other) in [(a, b), (b, a)] {
+                let (Ok(door), true) = (doors.get(door), character.contains(other)) else {
+                    continue;
+                };
+                requests.send(LoadLevel { identifier: door.level.clone() });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn test_map() -> LdtkMap {
+        let level = |identifier: &str, iid: &str, world_x: i32| serde_json::json!({
+            "identifier": identifier,
+            "iid": iid,
+            "worldX": world_x,
+            "worldY": 0,
+            "pxWid": 256,
+            "pxHei": 256,
+        });
+        let project = serde_json::json!({
+            "defs": { "tilesets": [] },
+            "levels": [level("First", "first", 0), level("Second", "second", 1024)],
+        });
+        LdtkMap { project: serde_json::from_value(project).unwrap(), tilesets: HashMap::default() }
+    }
+
+    fn load(world: &mut World, identifier: &str) {
+        world.resource_mut::<Events<LoadLevel>>().send(LoadLevel { identifier: identifier.into() });
+        world.run_system_once(LevelManager::load);
+    }
+
+    #[test]
+    fn nothing_from_the_last_level_survives_a_load() {
+        let mut world = World::new();
+        let mut maps = Assets::<LdtkMap>::default();
+        let map = maps.add(test_map());
+        world.insert_resource(maps);
+        world.insert_resource(LevelManager::new(map));
+        world.init_resource::<Events<LoadLevel>>();
+
+        load(&mut world, "First");
+        let first: Vec<Entity> = world.query_filtered::<Entity, With<LdtkLevel>>().iter(&world).collect();
+        assert_eq!(first.len(), 1);
+        let monster = world.spawn(LevelScoped).with_children(|builder| {
+            builder.spawn_empty();
+        }).id();
+        let bullet = world.spawn(LevelScoped).id();
+
+        load(&mut world, "Second");
+        assert!(world.get_entity(monster).is_none());
+        assert!(world.get_entity(bullet).is_none());
+        assert_eq!(world.query_filtered::<(), With<LevelScoped>>().iter(&world).count(), 0);
+        assert!(world.get::<Unload>(first[0]).is_some());
+
+        let loaded: Vec<&LdtkLevel> = world.query_filtered::<&LdtkLevel, Without<Unload>>().iter(&world).collect();
+        assert_eq!(loaded.len(), 1);
+        assert!(matches!(&loaded[0].source, LevelSource::Map(identifier) if identifier == "Second"));
+    }
+}
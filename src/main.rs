@@ -1,7 +1,29 @@
 mod character;
+mod chunk;
+mod city;
 mod monster;
 mod song;
 mod animation;
+mod beat;
+mod beat_terrain;
+mod beat_tween;
+mod boss;
+mod bullet;
+mod camera;
+mod collision;
+mod combat;
+mod death;
+mod elite;
+mod ldtk;
+mod level;
+mod nav;
+mod pickup;
+mod props;
+mod status;
+mod steering;
+mod telegraph;
+mod terrain;
+mod tween;
 
 use bevy::{prelude::*, diagnostic::*};
 use bevy_rapier2d::prelude::*;
@@ -9,6 +31,24 @@ use character::CharacterPlugin;
 use monster::MonsterPlugin;
 use song::SongPlugin;
 use animation::AnimationPlugin;
+use beat::BeatPlugin;
+use beat_terrain::BeatTerrainPlugin;
+use beat_tween::BeatTweenPlugin;
+use boss::BossPlugin;
+use bullet::BulletPlugin;
+use camera::CameraPlugin;
+use chunk::ChunkPlugin;
+use combat::CombatPlugin;
+use death::DeathPlugin;
+use elite::ElitePlugin;
+use level::LevelPlugin;
+use nav::NavPlugin;
+use pickup::PickupPlugin;
+use props::PropPlugin;
+use status::StatusPlugin;
+use steering::SteeringPlugin;
+use telegraph::TelegraphPlugin;
+use terrain::TerrainPlugin;
 
 fn main() {
     App::new()
@@ -16,12 +56,8 @@ fn main() {
         .add_plugins((LogDiagnosticsPlugin::default(), FrameTimeDiagnosticsPlugin::default()))
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0))
         //.add_plugins(RapierDebugRenderPlugin::default())
-        .add_plugins((SongPlugin, CharacterPlugin, MonsterPlugin, AnimationPlugin))
-        .add_systems(Startup, start_camera)
+        .add_plugins((SongPlugin, BeatPlugin, BeatTweenPlugin, TerrainPlugin, ChunkPlugin, NavPlugin, SteeringPlugin, CharacterPlugin, MonsterPlugin, BulletPlugin))
+        .add_plugins((CombatPlugin, StatusPlugin, DeathPlugin, ElitePlugin, BossPlugin, TelegraphPlugin, PickupPlugin, PropPlugin, LevelPlugin, BeatTerrainPlugin, CameraPlugin, AnimationPlugin))
         .insert_resource(Time::<Fixed>::from_seconds(1.0 / 60.0))
         .run();
 }
-
-fn start_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
-}
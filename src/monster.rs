@@ -2,21 +2,62 @@ use std::f32::consts::PI;
 
 use bevy::{prelude::*, ecs::system::EntityCommands};
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{character::Character, song::SongPlayback};
+use crate::{
+    beat::{Beat, BeatBehavior, BeatBehaviorPlugin, BeatContext, BeatScale, BeatSet, BeatSpin, BeatTarget},
+    beat_tween::{BeatTween, TweenMode},
+    boss::{Boss, BossPhase, PhaseTrigger},
+    bullet::{BeatShooter, BulletPattern},
+    character::Character,
+    collision,
+    combat::{CombatSet, Health, Invulnerable, Killed, Reward},
+    death::Corpse,
+    elite::{Elite, Modifier},
+    level::LevelScoped,
+    nav::{FlowFollower, NavPath},
+    song::SongPlayback,
+    steering::{flee, orbit, seek, Flocking, Steering, SteeringKind},
+    telegraph::{Telegraph, Telegraphed, TelegraphedPlugin},
+    terrain::RegisterLdtkEntity,
+    tween::{Curve, Ease, Keyframe, Tween, TweenType},
+};
 use rand::prelude::*;
 
 pub struct MonsterPlugin;
 impl Plugin for MonsterPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
+            .register_type::<BeatChase>()
+            .register_type::<BeatLineDash>()
+            .register_type::<BeatOrbit>()
+            .register_type::<BeatKeepRange>()
+            .add_plugins((
+                BeatBehaviorPlugin::<BeatChase>::default(),
+                BeatBehaviorPlugin::<BeatLineDash>::default(),
+                BeatBehaviorPlugin::<BeatOrbit>::default(),
+                BeatBehaviorPlugin::<BeatKeepRange>::default(),
+                TelegraphedPlugin::<BeatLineDash>::default(),
+            ))
+            .register_ldtk_entity("MonsterSpawn", |commands, instance| {
+                commands.insert(MonsterSpawn {
+                    archetype: instance.field("archetype").and_then(|value| value.as_u64()).map(|archetype| archetype as usize),
+                    every: instance.field("every").and_then(|value| value.as_u64()).map_or(8, |every| every.max(1) as usize),
+                });
+            })
             .add_systems(Startup, load_monster_spawner)
-            .add_systems(Update, (spawn_monster, BeatChase::system, BeatScale::system, BeatSpin::system, BeatLineDash::system));
+            .add_systems(Update, (spawn_monster, MonsterSpawn::system, SpawnMarker::system, spawn_boss))
+            .add_systems(Update, Spawning::system.in_set(BeatSet::Apply))
+            .add_systems(Update, split_on_death.after(CombatSet::Damage).before(CombatSet::Despawn));
     }
 }
 
 type EntitySpawnFn = Box<dyn FnMut(&mut EntityCommands) + Send + Sync>;
 
+/// Which of the [`MonsterSpawner`]'s monsters an entity was spawned as.
+#[derive(Component)]
+struct Archetype(usize);
+
 #[derive(Component)]
 struct MonsterSpawner {
     timer: Timer,
@@ -35,9 +76,18 @@ fn load_monster_spawner(
                 skeleton_spawner(&asset_server, &mut texture_atlases),
                 bird_spawner(&asset_server, &mut texture_atlases),
                 lizard_spawner(&asset_server, &mut texture_atlases),
+                archer_spawner(&asset_server, &mut texture_atlases),
+                mage_spawner(&asset_server, &mut texture_atlases),
             ],
         }
     );
+    commands.spawn(
+        BossSpawner {
+            marker: "boss".into(),
+            boss: lich_king_spawner(&asset_server, &mut texture_atlases),
+            spawned: false,
+        }
+    );
 }
 
 fn skeleton_spawner(
@@ -50,18 +100,25 @@ fn skeleton_spawner(
 
     Box::new(move |commands| {
         commands.insert((
-            BeatChase { tween: Tween { ttype: TweenType::Square, a: 60.0 * 3.0, b: 60.0 * 0.0, ..default()}, on_beat: 0, freq: 1},
+            BeatChase { beat: Beat { tween: Tween { ttype: TweenType::Square, a: 60.0 * 3.0, b: 60.0 * 0.0, ..default()}, freq: 1, on_beat: 0 } },
             RigidBody::Dynamic,
             Collider::ball(15.0),
+            CollisionGroups::new(collision::MONSTER, Group::ALL),
             Restitution::coefficient(0.0),
             Velocity::default(),
+            Steering::default(),
+            FlowFollower::default(),
+            Flocking { radius: 48.0, separation: 90.0, alignment: 0.1, cohesion: 10.0 },
+            Health::new(3.0),
+            Reward { points: 3 },
+            Corpse { beats: 8 },
             ColliderMassProperties::Mass(5.0),
             GravityScale(0.0),
             LockedAxes::ROTATION_LOCKED,
         )).with_children(|builder| {
             builder.spawn((
-                BeatSpin { freq: 4, on_beat: 1, tween: Tween { ttype: TweenType::Sawtooth, a: 0.0, b: 2.0 * PI, start: 0.5, ..default()}},
-                BeatScale { tween: Tween { ttype: TweenType::Square, a: 1.2, b: 1.0, ..default()}, on_beat: 0, freq: 1},
+                BeatSpin { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 0.0, b: 2.0 * PI, start: 0.5, ease: Ease::Out(Curve::Expo), ..default()}, freq: 4, on_beat: 1 } },
+                BeatScale { beat: Beat { tween: Tween { ttype: TweenType::Square, a: 1.2, b: 1.0, ..default()}, freq: 1, on_beat: 0 } },
                 SpriteSheetBundle {
                     texture_atlas: texture_atlas_handle.clone(),
                     sprite: TextureAtlasSprite::new(0),
@@ -82,18 +139,30 @@ fn bird_spawner(
 
     Box::new(move |commands| {
         commands.insert((
-            BeatChase { tween: Tween { ttype: TweenType::Sawtooth, a: 60.0 * 4.0, b: 60.0 * 2.0, ..default()}, on_beat: 0, freq: 1},
+            BeatChase { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 60.0 * 4.0, b: 60.0 * 2.0, ..default()}, freq: 1, on_beat: 0 } },
+            BeatOrbit { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 60.0 * 2.0, b: 0.0, ..default()}, freq: 2, on_beat: 1 }, clockwise: false },
             RigidBody::Dynamic,
             Collider::ball(15.0),
+            CollisionGroups::new(collision::MONSTER, Group::ALL),
             Restitution::coefficient(0.0),
             Velocity::default(),
+            Steering::default(),
+            Flocking { radius: 64.0, separation: 60.0, alignment: 0.5, cohesion: 30.0 },
+            Health::new(1.0),
+            Reward { points: 1 },
             ColliderMassProperties::Mass(0.1),
             GravityScale(0.0),
             LockedAxes::ROTATION_LOCKED,
         )).with_children(|builder| {
             builder.spawn((
-                BeatScale { tween: Tween { ttype: TweenType::Triangle, a: 1.0, b: 1.1, mid: 0.2, ..default()}, on_beat: 0, freq: 1},
-                BeatSpin { freq: 4, on_beat: 3, tween: Tween { ttype: TweenType::Sawtooth, a: 2.0 * PI, b: 0.0, ..default()}},
+                BeatScale { beat: Beat { tween: Tween { ttype: TweenType::Triangle, a: 1.0, b: 1.1, mid: 0.2, ease: Ease::Out(Curve::Elastic), ..default()}, freq: 1, on_beat: 0 } },
+                BeatSpin { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 2.0 * PI, b: 0.0, ..default()}, freq: 4, on_beat: 3 } },
+                BeatTween::new(
+                    Beat { tween: Tween { ttype: TweenType::Triangle, a: 0.0, b: 6.0, ease: Ease::InOut(Curve::Quad), ..default()}, freq: 1, on_beat: 0 },
+                    "Transform",
+                    "translation.y",
+                    TweenMode::Add,
+                ),
                 SpriteSheetBundle {
                     texture_atlas: texture_atlas_handle.clone(),
                     sprite: TextureAtlasSprite::new(0),
@@ -114,18 +183,82 @@ fn lizard_spawner(
 
     Box::new(move |commands| {
         commands.insert((
-            BeatLineDash { tween: Tween { ttype: TweenType::Triangle, a: 60.0 * 6.0, b: 12.0 * 16.0, ..default()}, on_beat: 0, freq: 2},
-            BeatScale { tween: Tween { ttype: TweenType::Sawtooth, a: 2.5, b: 1.0, ..default()}, on_beat: 1, freq: 2},
+            BeatLineDash {
+                beat: Beat {
+                    tween: Tween {
+                        ttype: TweenType::Keyframes(vec![
+                            Keyframe::new(0.0, 60.0 * 2.0, Ease::Linear),
+                            Keyframe::new(0.2, 60.0 * 8.0, Ease::Out(Curve::Quad)),
+                            Keyframe::new(1.0, 0.0, Ease::In(Curve::Cubic)),
+                        ]),
+                        ..default()
+                    },
+                    freq: 2,
+                    on_beat: 0,
+                },
+                lock: None,
+            },
+            Telegraph::Line { length: 140.0, color: Color::rgba(1.0, 0.3, 0.2, 0.4) },
+            BeatScale { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 2.5, b: 1.0, ease: Ease::Out(Curve::Bounce), ..default()}, freq: 2, on_beat: 1 } },
             RigidBody::Dynamic,
             Collider::ball(15.0),
+            CollisionGroups::new(collision::MONSTER, Group::ALL),
+            Restitution::coefficient(0.0),
+            Velocity::default(),
+            Steering::default(),
+            NavPath::default(),
+            Flocking { radius: 40.0, separation: 90.0, alignment: 0.0, cohesion: 0.0 },
+            Health::new(2.0),
+            Reward { points: 2 },
+            Corpse { beats: 8 },
+            ColliderMassProperties::Mass(1.0),
+            GravityScale(0.0),
+            LockedAxes::ROTATION_LOCKED,
+        )).with_children(|builder| {
+            builder.spawn((
+                BeatSpin { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 2.0 * PI, b: 0.0, ..default()}, freq: 2, on_beat: 1 } },
+                SpriteSheetBundle {
+                    texture_atlas: texture_atlas_handle.clone(),
+                    sprite: TextureAtlasSprite::new(0),
+                    ..default()
+                },
+            ));
+        });
+    })
+}
+
+fn archer_spawner(
+    asset_server: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlas>,
+) -> EntitySpawnFn {
+    let texture_handle: Handle<Image> = asset_server.load(r"sprites/swishs-monster-pack/24px by 24px/swish_skeleton_archer.png");
+    let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(24.0, 24.0), 1, 1, None, None);
+    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+
+    Box::new(move |commands| {
+        commands.insert((
+            BeatKeepRange { beat: Beat { tween: Tween { ttype: TweenType::Square, a: 60.0 * 2.0, b: 0.0, ..default()}, freq: 1, on_beat: 0 }, range: 250.0, tolerance: 50.0 },
+            BeatShooter::new(
+                Beat { tween: Tween { a: 60.0 * 3.0, b: 60.0 * 3.0, ..default()}, freq: 4, on_beat: 2 },
+                BulletPattern::Aimed { count: 3, spread: 0.5 },
+                1,
+            ),
+            Telegraph::Flash(Color::ORANGE_RED),
+            RigidBody::Dynamic,
+            Collider::ball(12.0),
+            CollisionGroups::new(collision::MONSTER, Group::ALL),
             Restitution::coefficient(0.0),
             Velocity::default(),
+            Steering::default(),
+            Flocking { radius: 80.0, separation: 60.0, alignment: 0.0, cohesion: 0.0 },
+            Health::new(2.0),
+            Reward { points: 2 },
             ColliderMassProperties::Mass(1.0),
             GravityScale(0.0),
             LockedAxes::ROTATION_LOCKED,
         )).with_children(|builder| {
             builder.spawn((
-                BeatSpin { freq: 2, on_beat: 1, tween: Tween { ttype: TweenType::Sawtooth, a: 2.0 * PI, b: 0.0, ..default()}},
+                BeatScale { beat: Beat { tween: Tween { ttype: TweenType::Triangle, a: 1.0, b: 1.3, mid: 0.3, ease: Ease::Out(Curve::Back), ..default()}, freq: 4, on_beat: 2 } },
                 SpriteSheetBundle {
                     texture_atlas: texture_atlas_handle.clone(),
                     sprite: TextureAtlasSprite::new(0),
@@ -136,11 +269,146 @@ fn lizard_spawner(
     })
 }
 
+fn mage_spawner(
+    asset_server: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlas>,
+) -> EntitySpawnFn {
+    let texture_handle: Handle<Image> = asset_server.load(r"sprites/swishs-monster-pack/24px by 24px/swish_skeleton_mage.png");
+    let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(24.0, 24.0), 1, 1, None, None);
+    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+
+    Box::new(move |commands| {
+        commands.insert((
+            BeatKeepRange { beat: Beat { tween: Tween { ttype: TweenType::Square, a: 60.0 * 1.5, b: 0.0, ..default()}, freq: 2, on_beat: 0 }, range: 350.0, tolerance: 80.0 },
+            BeatShooter::new(
+                Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 60.0 * 1.5, b: 60.0 * 2.5, ..default()}, freq: 8, on_beat: 4 },
+                BulletPattern::Spiral { arms: 3, step: PI / 8.0 },
+                4,
+            ),
+            Telegraph::Flash(Color::VIOLET),
+            RigidBody::Dynamic,
+            Collider::ball(12.0),
+            CollisionGroups::new(collision::MONSTER, Group::ALL),
+            Restitution::coefficient(0.0),
+            Velocity::default(),
+            Steering::default(),
+            Flocking { radius: 120.0, separation: 60.0, alignment: 0.0, cohesion: 0.0 },
+            Health::new(3.0),
+            Reward { points: 3 },
+            ColliderMassProperties::Mass(1.0),
+            GravityScale(0.0),
+            LockedAxes::ROTATION_LOCKED,
+        )).with_children(|builder| {
+            builder.spawn((
+                BeatSpin { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 0.0, b: 2.0 * PI, ease: Ease::InOut(Curve::Cubic), ..default()}, freq: 8, on_beat: 4 } },
+                SpriteSheetBundle {
+                    texture_atlas: texture_atlas_handle.clone(),
+                    sprite: TextureAtlasSprite::new(0),
+                    ..default()
+                },
+            ));
+        });
+    })
+}
+
+fn lich_king_spawner(
+    asset_server: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlas>,
+) -> EntitySpawnFn {
+    let texture_handle: Handle<Image> = asset_server.load(r"sprites/swishs-monster-pack/30px by 30px/swish_lich_king.png");
+    let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(30.0, 30.0), 1, 1, None, None);
+    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+
+    Box::new(move |commands| {
+        commands.insert((
+            Boss::new(vec![
+                BossPhase {
+                    until: vec![PhaseTrigger::HealthBelow(0.6)],
+                    enter: Box::new(|commands| {
+                        commands.insert((
+                            BeatChase { beat: Beat { tween: Tween { ttype: TweenType::Square, a: 60.0, b: 0.0, ..default()}, freq: 1, on_beat: 0 } },
+                            BeatShooter::new(
+                                Beat { tween: Tween { a: 60.0 * 2.5, b: 60.0 * 2.5, ..default()}, freq: 4, on_beat: 3 },
+                                BulletPattern::Ring { count: 12 },
+                                1,
+                            ),
+                        ));
+                    }),
+                    exit: Box::new(|commands| {
+                        commands.remove::<(BeatChase, BeatShooter)>();
+                    }),
+                },
+                BossPhase {
+                    until: vec![PhaseTrigger::HealthBelow(0.25), PhaseTrigger::Marker("boss-rage".into())],
+                    enter: Box::new(|commands| {
+                        commands.insert((
+                            BeatLineDash { beat: Beat { tween: Tween { ttype: TweenType::Triangle, a: 60.0 * 4.0, b: 60.0 * 8.0, ..default()}, freq: 4, on_beat: 0 }, lock: None },
+                            BeatShooter::new(
+                                Beat { tween: Tween { a: 60.0 * 2.0, b: 60.0 * 2.0, ..default()}, freq: 4, on_beat: 2 },
+                                BulletPattern::Spiral { arms: 4, step: PI / 12.0 },
+                                4,
+                            ),
+                        ));
+                    }),
+                    exit: Box::new(|commands| {
+                        commands.remove::<(BeatLineDash, BeatShooter)>();
+                    }),
+                },
+                BossPhase {
+                    until: vec![],
+                    enter: Box::new(|commands| {
+                        commands.insert((
+                            BeatChase { beat: Beat { tween: Tween { ttype: TweenType::Sawtooth, a: 60.0 * 3.0, b: 60.0, ..default()}, freq: 1, on_beat: 0 } },
+                            BeatShooter::new(
+                                Beat { tween: Tween { a: 60.0 * 3.5, b: 60.0 * 3.5, ..default()}, freq: 2, on_beat: 1 },
+                                BulletPattern::Aimed { count: 5, spread: 1.0 },
+                                2,
+                            ),
+                        ));
+                    }),
+                    exit: Box::new(|commands| {
+                        commands.remove::<(BeatChase, BeatShooter)>();
+                    }),
+                },
+            ]),
+            Telegraph::WindUp(Tween { ttype: TweenType::Sawtooth, a: 1.0, b: 1.2, ease: Ease::In(Curve::Back), ..default()}),
+            Health::new(60.0),
+            Reward { points: 100 },
+            Corpse { beats: 32 },
+            RigidBody::Dynamic,
+            Collider::ball(30.0),
+            CollisionGroups::new(collision::MONSTER, Group::ALL),
+            Restitution::coefficient(0.0),
+            Velocity::default(),
+            Steering::default(),
+            NavPath::default(),
+            ColliderMassProperties::Mass(50.0),
+            GravityScale(0.0),
+            LockedAxes::ROTATION_LOCKED,
+        )).with_children(|builder| {
+            builder.spawn(SpriteSheetBundle {
+                texture_atlas: texture_atlas_handle.clone(),
+                sprite: TextureAtlasSprite::new(0),
+                transform: Transform::from_scale(Vec3::new(3.0, 3.0, 1.0)),
+                ..default()
+            });
+            for offset in [Vec2::new(-36.0, -20.0), Vec2::new(36.0, -20.0)] {
+                builder.spawn((
+                    TransformBundle::from(Transform::from_translation(offset.extend(0.0))),
+                    Collider::ball(18.0),
+                    CollisionGroups::new(collision::MONSTER, Group::ALL),
+                ));
+            }
+        });
+    })
+}
+
 fn spawn_monster(
     mut commands: Commands,
     mut spawner: Query<&mut MonsterSpawner>,
     time: Res<Time>,
     character: Query<&Transform, With<Character>>,
+    song: Res<SongPlayback>,
 ) {
     let mut spawner = spawner.single_mut();
     spawner.timer.tick(time.delta());
@@ -155,184 +423,319 @@ fn spawn_monster(
     let character_pos = character.single().translation;
     let spawn_pos = character_pos + offset.extend(0.0);
 
-    let mut entity_commands = commands.spawn((
-        SpatialBundle::from(Transform::from_translation(spawn_pos)),
-    ));
-    if let Some(monster) = spawner.monsters.choose_mut(&mut rng) {
-        monster(&mut entity_commands);
-    } else {
-        println!("Error, no monsters found to spawn")
+    if spawner.monsters.is_empty() {
+        warn!("No monsters to spawn");
+        return;
     }
+    let marker = SpawnMarker {
+        archetype: rng.gen_range(0..spawner.monsters.len()),
+        elite: Elite::roll(&mut rng),
+        beat: song.beat_count + SPAWN_MARKER_BEATS,
+    };
+    marker.spawn(&mut commands, spawn_pos.truncate());
 }
 
-#[derive(Component, Clone)]
-struct BeatLineDash {
-    tween: Tween,
-    freq: usize,
-    on_beat: usize,
+/// A spawn point from the map, putting down a monster every `every` beats.
+#[derive(Component, Debug)]
+pub struct MonsterSpawn {
+    /// Which of the [`MonsterSpawner`]'s monsters, random if unset
+    pub archetype: Option<usize>,
+    pub every: usize,
 }
-#[derive(Component, Clone)]
-#[component(storage = "SparseSet")]
-struct DirectionLocked {
-    dir: Vec2,
-}
-impl BeatLineDash {
+
+impl MonsterSpawn {
     fn system(
         mut commands: Commands,
-        mut monsters: Query<(Entity, &Transform, &mut Velocity, &BeatLineDash, Option<&mut DirectionLocked>)>,
-        character: Query<&Transform, With<Character>>,
+        spawns: Query<(&MonsterSpawn, &GlobalTransform)>,
+        spawner: Query<&MonsterSpawner>,
         song: Res<SongPlayback>,
     ) {
-        let character = character.single().translation;
-        let p = song.bpm_timer.percent();
-        // Travel 4 pixels per tick (pixels per meter is set to 1.0)
-        for (entity, transform, mut velocity, config, lock) in monsters.iter_mut() {
-            if song.beat_count % config.freq == config.on_beat {
-                let target_speed = config.tween.tween(p);
-                let flat_direction = lock.map_or_else(|| {
-                    let target_direction = character - transform.translation;
-                    let new_lock = if target_direction.x.abs() > target_direction.y.abs() {
-                        Vec2::new(target_direction.x, 0.0)
-                    } else {
-                        Vec2::new(0.0, target_direction.y)
-                    };
-                    commands.entity(entity).insert(DirectionLocked { dir: new_lock });
-                    new_lock
-                }, |l| l.dir);
-                
-                velocity.linvel = flat_direction.clamp_length(target_speed, target_speed)
-            } else {
-                if lock.is_some() {
-                    commands.entity(entity).remove::<DirectionLocked>();
-                }
-                velocity.linvel = Vec2::ZERO;
+        if !song.bpm_timer.just_finished() {
+            return;
+        }
+        let monsters = spawner.single().monsters.len();
+        let mut rng = rand::thread_rng();
+        for (spawn, transform) in spawns.iter() {
+            if song.beat_count % spawn.every != 0 || monsters == 0 {
+                continue;
             }
+            let marker = SpawnMarker {
+                archetype: spawn.archetype.filter(|&archetype| archetype < monsters).unwrap_or_else(|| rng.gen_range(0..monsters)),
+                elite: Elite::roll(&mut rng),
+                beat: song.beat_count + SPAWN_MARKER_BEATS,
+            };
+            marker.spawn(&mut commands, transform.translation().truncate());
         }
     }
 }
 
-#[derive(Component, Clone)]
-struct BeatChase {
-    tween: Tween,
-    freq: usize,
-    on_beat: usize,
+/// How many beats a spawn marker shows on the ground before its monster
+/// appears.
+const SPAWN_MARKER_BEATS: usize = 2;
+
+#[derive(Component)]
+struct SpawnMarker {
+    archetype: usize,
+    elite: Option<Elite>,
+    /// The beat the monster appears on
+    beat: usize,
 }
-impl BeatChase {
+
+impl SpawnMarker {
+    fn spawn(self, commands: &mut Commands, position: Vec2) {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite { color: Color::rgba(1.0, 0.2, 0.2, 0.5), custom_size: Some(Vec2::splat(24.0)), ..default() },
+                transform: Transform::from_translation(position.extend(0.3)).with_rotation(Quat::from_rotation_z(PI / 4.0)),
+                ..default()
+            },
+            BeatScale { beat: Beat { tween: Tween { ttype: TweenType::Triangle, a: 1.0, b: 1.4, mid: 0.2, ease: Ease::Out(Curve::Quad), ..default()}, freq: 1, on_beat: 0 } },
+            LevelScoped,
+            self,
+        ));
+    }
+
     fn system(
-        mut monsters: Query<(&Transform, &mut Velocity, &BeatChase)>,
-        character: Query<&Transform, With<Character>>,
+        mut commands: Commands,
+        mut markers: Query<(Entity, &mut SpawnMarker, &Transform)>,
+        mut spawner: Query<&mut MonsterSpawner>,
         song: Res<SongPlayback>,
     ) {
-        let character = character.single().translation;
-        let p = song.bpm_timer.percent();
-        // Travel 4 pixels per tick (pixels per meter is set to 1.0)
-        for (transform, mut velocity, config) in monsters.iter_mut() {
-            if song.beat_count % config.freq == config.on_beat {
-                let target_speed = config.tween.tween(p);
-                let target_velocity = (character - transform.translation).clamp_length(target_speed, target_speed);
-                // Fix velocity
-                velocity.linvel = target_velocity.truncate();
-            } else {
-                velocity.linvel = Vec2::ZERO;
+        if !song.bpm_timer.just_finished() {
+            return;
+        }
+        let mut spawner = spawner.single_mut();
+        for (entity, mut marker, transform) in markers.iter_mut() {
+            if song.beat_count < marker.beat {
+                continue;
+            }
+            commands.entity(entity).despawn();
+            let position = transform.translation.truncate().extend(0.0);
+            let mut entity_commands = spawn_archetype(&mut commands, &mut spawner, marker.archetype, position, &song);
+            if let Some(elite) = marker.elite.take() {
+                entity_commands.insert(elite);
             }
         }
     }
 }
 
-#[derive(Component, Clone)]
-struct BeatScale {
-    tween: Tween,
-    freq: usize,
-    on_beat: usize,
+/// A monster scaling in over its first beat, which can't hurt or be hurt
+/// until it is done.
+#[derive(Component)]
+pub struct Spawning {
+    start: f32,
 }
-impl BeatScale {
+
+impl Spawning {
+    fn new(song: &SongPlayback) -> Self {
+        Self { start: song.beat_count as f32 + song.bpm_timer.percent() }
+    }
+
     fn system(
-        mut monsters: Query<(&mut Transform, &BeatScale)>,
+        mut commands: Commands,
+        mut spawning: Query<(Entity, &Spawning, &mut Transform)>,
         song: Res<SongPlayback>,
     ) {
-        let p = song.bpm_timer.percent();
-        for (mut transform, config) in monsters.iter_mut() {
-            if song.beat_count % config.freq == config.on_beat {
-                let size = config.tween.tween(p);
-                transform.scale = Vec3::new(size, size, 1.0);
-            } else {
+        let now = song.beat_count as f32 + song.bpm_timer.percent();
+        for (entity, spawning, mut transform) in spawning.iter_mut() {
+            let progress = now - spawning.start;
+            if progress >= 1.0 {
                 transform.scale = Vec3::ONE;
+                commands.entity(entity).remove::<(Spawning, Invulnerable, ColliderDisabled)>();
+            } else {
+                // Rapier can't take a zero scale
+                let scale = Ease::Out(Curve::Back).apply(progress).max(0.01);
+                transform.scale = Vec3::new(scale, scale, 1.0);
             }
         }
     }
 }
 
+/// Spawns monster `archetype`, which then scales in through [`Spawning`].
+fn spawn_archetype<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    spawner: &mut MonsterSpawner,
+    archetype: usize,
+    position: Vec3,
+    song: &SongPlayback,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut entity_commands = commands.spawn((
+        SpatialBundle::from(Transform::from_translation(position).with_scale(Vec3::new(0.01, 0.01, 1.0))),
+        Archetype(archetype),
+        Spawning::new(song),
+        Invulnerable,
+        ColliderDisabled,
+        LevelScoped,
+    ));
+    (spawner.monsters[archetype])(&mut entity_commands);
+    entity_commands
+}
 
-#[derive(Component, Clone)]
-struct BeatSpin {
-    tween: Tween,
-    freq: usize,
-    on_beat: usize,
+fn split_on_death(
+    mut commands: Commands,
+    mut killed: EventReader<Killed>,
+    mut spawner: Query<&mut MonsterSpawner>,
+    monsters: Query<(&Transform, &Archetype, &Elite)>,
+    song: Res<SongPlayback>,
+) {
+    let mut spawner = spawner.single_mut();
+    for event in killed.read() {
+        let Ok((transform, archetype, elite)) = monsters.get(event.entity) else {
+            continue;
+        };
+        if !elite.has(Modifier::Splits) {
+            continue;
+        }
+        for offset in [Vec3::new(-20.0, 0.0, 0.0), Vec3::new(20.0, 0.0, 0.0)] {
+            spawn_archetype(&mut commands, &mut spawner, archetype.0, transform.translation + offset, &song);
+        }
+    }
 }
-impl BeatSpin {
-    fn system(
-        mut monsters: Query<(&mut Transform, &BeatSpin)>,
-        song: Res<SongPlayback>,
-    ) {
-        let p = song.bpm_timer.percent();
-        for (mut transform, config) in monsters.iter_mut() {
-            if song.beat_count % config.freq == config.on_beat {
-                let radians = config.tween.tween(p);
-                transform.rotation = Quat::from_rotation_z(radians);
-            } else {
-                transform.rotation = Quat::from_rotation_z(0.0);
-            }
+
+#[derive(Component)]
+struct BossSpawner {
+    marker: String,
+    boss: EntitySpawnFn,
+    spawned: bool,
+}
+
+fn spawn_boss(
+    mut commands: Commands,
+    mut spawners: Query<&mut BossSpawner>,
+    song: Res<SongPlayback>,
+    character: Query<&Transform, With<Character>>,
+) {
+    for mut spawner in spawners.iter_mut() {
+        if spawner.spawned || !song.reached(&spawner.marker) {
+            continue;
         }
+        spawner.spawned = true;
+
+        let spawn_pos = character.single().translation + Vec3::new(0.0, 400.0, 0.0);
+        let mut entity_commands = commands.spawn((
+            SpatialBundle::from(Transform::from_translation(spawn_pos)),
+            LevelScoped,
+        ));
+        (spawner.boss)(&mut entity_commands);
     }
 }
 
-#[derive(Clone)]
-pub struct Tween {
-    ttype: TweenType,
-    a: f32,
-    b: f32,
-    start: f32,
-    mid: f32,
-    end: f32,
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatLineDash {
+    pub beat: Beat,
+    #[reflect(ignore)]
+    #[serde(skip)]
+    lock: Option<(usize, Vec2)>,
 }
+impl BeatLineDash {
+    fn lock_towards(&mut self, beat: usize, target_direction: Vec2) -> Vec2 {
+        let direction = if target_direction.x.abs() > target_direction.y.abs() {
+            Vec2::new(target_direction.x, 0.0)
+        } else {
+            Vec2::new(0.0, target_direction.y)
+        }.normalize_or_zero();
+        self.lock = Some((beat, direction));
+        direction
+    }
+}
+impl BeatBehavior for BeatLineDash {
+    type Target = (&'static Transform, &'static mut Steering);
+    const PRIORITY: u8 = 1;
+    const MOVEMENT: bool = true;
+
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
 
-impl Default for Tween {
-    fn default() -> Self {
-        Self { ttype: TweenType::Sawtooth, a: 1.0, b: 1.0, start: 0.0, mid: 0.5, end: 1.0 }
+    fn on_beat(&mut self, target_speed: f32, context: &BeatContext, (transform, mut steering): BeatTarget<Self>) {
+        let flat_direction = match self.lock {
+            Some((beat, direction)) if beat == context.beat => direction,
+            _ => self.lock_towards(context.beat, (context.target - transform.translation).truncate()),
+        };
+        steering.add(SteeringKind::Dash, Self::PRIORITY, 1.0, flat_direction * target_speed);
+    }
+
+    fn off_beat(&mut self, _context: &BeatContext, _target: BeatTarget<Self>) {}
+}
+impl Telegraphed for BeatLineDash {
+    fn preview(&mut self, beat: usize, position: Vec2, context: &BeatContext) -> Option<Vec2> {
+        self.beat.is_active(beat).then(|| self.lock_towards(beat, context.target.truncate() - position))
     }
 }
 
-#[derive(Copy, Clone)]
-pub enum TweenType {
-    Sawtooth,
-    Triangle,
-    Square,
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatChase {
+    pub beat: Beat,
 }
+impl BeatBehavior for BeatChase {
+    type Target = (&'static Transform, &'static mut Steering);
+    const MOVEMENT: bool = true;
 
-impl Tween {
-    fn tween(&self, p: f32) -> f32 {
-        if p < self.start { return self.a }
-        if p > self.end {
-            match self.ttype {
-                TweenType::Triangle => return self.a,
-                _ => return self.b,
-            }
-        }
-        match self.ttype {
-            TweenType::Sawtooth =>
-                lerp(self.a, self.b, p),
-            TweenType::Triangle =>
-                if p < self.mid { 
-                    lerp(self.a, self.b, p / self.mid)
-                } else {
-                    lerp(self.a, self.b, p / (1.0 - self.mid))
-                }
-            TweenType::Square => 
-                if p < self.mid { self.a } else { self.b },
-        }
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
+
+    fn on_beat(&mut self, target_speed: f32, context: &BeatContext, (transform, mut steering): BeatTarget<Self>) {
+        let target_velocity = seek(transform.translation.truncate(), context.target.truncate(), target_speed);
+        steering.add(SteeringKind::Seek, Self::PRIORITY, 1.0, target_velocity);
     }
+
+    fn off_beat(&mut self, _context: &BeatContext, _target: BeatTarget<Self>) {}
+}
+
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatOrbit {
+    pub beat: Beat,
+    pub clockwise: bool,
 }
+impl BeatBehavior for BeatOrbit {
+    type Target = (&'static Transform, &'static mut Steering);
+    const MOVEMENT: bool = true;
+
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
+
+    fn on_beat(&mut self, speed: f32, context: &BeatContext, (transform, mut steering): BeatTarget<Self>) {
+        let target_velocity = orbit(transform.translation.truncate(), context.character.truncate(), speed, self.clockwise);
+        steering.add(SteeringKind::Orbit, Self::PRIORITY, 1.0, target_velocity);
+    }
+
+    fn off_beat(&mut self, _context: &BeatContext, _target: BeatTarget<Self>) {}
+}
+
+/// Holds position `range` pixels away from the player, give or take
+/// `tolerance`, circling while in the band.
+#[derive(Component, Clone, Debug, Default, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct BeatKeepRange {
+    pub beat: Beat,
+    pub range: f32,
+    pub tolerance: f32,
+}
+impl BeatBehavior for BeatKeepRange {
+    type Target = (&'static Transform, &'static mut Steering);
+    const MOVEMENT: bool = true;
+
+    fn beat(&self) -> &Beat {
+        &self.beat
+    }
+
+    fn on_beat(&mut self, speed: f32, context: &BeatContext, (transform, mut steering): BeatTarget<Self>) {
+        let position = transform.translation.truncate();
+        let character = context.character.truncate();
+        let distance = position.distance(character);
+        if distance > self.range + self.tolerance {
+            steering.add(SteeringKind::Seek, Self::PRIORITY, 1.0, seek(position, character, speed));
+        } else if distance < self.range - self.tolerance {
+            steering.add(SteeringKind::Flee, Self::PRIORITY, 1.0, flee(position, character, speed));
+        } else {
+            steering.add(SteeringKind::Orbit, Self::PRIORITY, 0.5, orbit(position, character, speed, false));
+        }
+    }
 
-fn lerp(s: f32, e: f32, p: f32) -> f32 {
-    s + p * (e - s)
+    fn off_beat(&mut self, _context: &BeatContext, _target: BeatTarget<Self>) {}
 }
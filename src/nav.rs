@@ -0,0 +1,613 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{prelude::*, ecs::query::WorldQuery, utils::{HashMap, HashSet}};
+
+use crate::{beat::BeatSet, character::Character, collision, song::SongPlayback, terrain::TerrainCollider};
+
+pub struct NavPlugin;
+impl Plugin for NavPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .init_resource::<NavGrid>()
+            .init_resource::<FlowField>()
+            .add_systems(Update, (
+                NavGrid::rebuild,
+                (NavPath::update, NavPath::advance).chain(),
+                (FlowField::update, FlowFollower::sample).chain(),
+            ).chain().in_set(BeatSet::Prepare));
+    }
+}
+
+/// Cap on the cells A* expands before giving up, so an unreachable player
+/// can't stall a frame.
+const MAX_SEARCH: usize = 4096;
+
+/// How many cells around the player the flow field reaches.
+const FLOW_RADIUS: i32 = 72;
+
+const NEIGHBORS: [IVec2; 8] = [
+    IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y,
+    IVec2::ONE, IVec2::NEG_ONE, IVec2::new(1, -1), IVec2::new(-1, 1),
+];
+
+/// Which square cells of the world are blocked to monsters by
+/// [`TerrainCollider`]s.
+#[derive(Resource, Debug)]
+pub struct NavGrid {
+    cell_size: f32,
+    blocked: HashSet<IVec2>,
+    /// Cells blocked or cleared by the last rebuild
+    changed: HashSet<IVec2>,
+}
+
+impl Default for NavGrid {
+    fn default() -> Self {
+        Self::new(16.0)
+    }
+}
+
+impl NavGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self { cell_size, blocked: HashSet::default(), changed: HashSet::default() }
+    }
+
+    pub fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn center(&self, cell: IVec2) -> Vec2 {
+        (cell.as_vec2() + 0.5) * self.cell_size
+    }
+
+    pub fn block(&mut self, cell: IVec2) {
+        self.blocked.insert(cell);
+    }
+
+    pub fn is_blocked(&self, cell: IVec2) -> bool {
+        self.blocked.contains(&cell)
+    }
+
+    /// Whether a straight walk from `from` to `to` stays clear of blocked cells.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        if self.blocked.is_empty() {
+            return true;
+        }
+        let steps = (from.distance(to) / (self.cell_size * 0.25)).ceil().max(1.0) as usize;
+        (0..=steps).all(|i| !self.is_blocked(self.cell(from.lerp(to, i as f32 / steps as f32))))
+    }
+
+    /// A* over the 8-connected grid, without cutting blocked corners.
+    /// Returns the cells from `from` to `to`, both included.
+    pub fn find_path(&self, from: IVec2, to: IVec2) -> Option<Vec<IVec2>> {
+        if self.is_blocked(to) {
+            return None;
+        }
+        let heuristic = |cell: IVec2| {
+            let d = (cell - to).abs();
+            10 * d.x.max(d.y) + 4 * d.x.min(d.y)
+        };
+        let mut open = BinaryHeap::from([Reverse((heuristic(from), from.x, from.y))]);
+        let mut came_from: HashMap<IVec2, IVec2> = HashMap::default();
+        let mut cost: HashMap<IVec2, i32> = HashMap::from_iter([(from, 0)]);
+        let mut expanded = 0;
+
+        while let Some(Reverse((_, x, y))) = open.pop() {
+            let cell = IVec2::new(x, y);
+            if cell == to {
+                let mut path = vec![cell];
+                while let Some(&previous) = came_from.get(path.last().unwrap()) {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            expanded += 1;
+            if expanded > MAX_SEARCH {
+                return None;
+            }
+
+            for step in NEIGHBORS {
+                let Some(step_cost) = self.step_cost(cell, step) else {
+                    continue;
+                };
+                let next = cell + step;
+                let next_cost = cost[&cell] + step_cost;
+                if cost.get(&next).map_or(true, |&c| next_cost < c) {
+                    cost.insert(next, next_cost);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((next_cost + heuristic(next), next.x, next.y)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Cost of moving one cell by `step`, `None` if the move is blocked or
+    /// cuts a blocked corner.
+    fn step_cost(&self, cell: IVec2, step: IVec2) -> Option<i32> {
+        if self.is_blocked(cell + step) {
+            return None;
+        }
+        if step.x == 0 || step.y == 0 {
+            return Some(base_cost(step));
+        }
+        let cuts_corner = self.is_blocked(cell + IVec2::new(step.x, 0)) || self.is_blocked(cell + IVec2::new(0, step.y));
+        (!cuts_corner).then_some(base_cost(step))
+    }
+
+    /// Waypoints from `from` to `to`, skipping every cell that can be cut
+    /// straight across.
+    pub fn waypoints(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        if self.line_of_sight(from, to) {
+            return Some(vec![to]);
+        }
+        let cells = self.find_path(self.cell(from), self.cell(to))?;
+        let mut points: Vec<Vec2> = cells.iter().map(|&cell| self.center(cell)).collect();
+        *points.last_mut().unwrap() = to;
+
+        let mut waypoints = Vec::new();
+        let mut position = from;
+        let mut i = 0;
+        while i < points.len() - 1 {
+            let mut furthest = i;
+            while furthest + 1 < points.len() && self.line_of_sight(position, points[furthest + 1]) {
+                furthest += 1;
+            }
+            position = points[furthest];
+            waypoints.push(position);
+            i = furthest.max(i + 1);
+        }
+        Some(waypoints)
+    }
+
+    fn rebuild(
+        mut grid: ResMut<NavGrid>,
+        changed: Query<(), (With<TerrainCollider>, Or<(Changed<TerrainCollider>, Changed<GlobalTransform>)>)>,
+        mut removed: RemovedComponents<TerrainCollider>,
+        colliders: Query<(&TerrainCollider, &GlobalTransform)>,
+    ) {
+        let removed = removed.read().count() > 0;
+        if changed.is_empty() && !removed {
+            return;
+        }
+
+        let previous = std::mem::take(&mut grid.blocked);
+        for (collider, transform) in colliders.iter() {
+            if !collider.blocks.contains(collision::MONSTER) {
+                continue;
+            }
+            for rect in &collider.rects {
+                let min = transform.transform_point(rect.min.extend(0.0)).truncate();
+                let max = transform.transform_point(rect.max.extend(0.0)).truncate();
+                let min = grid.cell(min + 0.01);
+                let max = grid.cell(max - 0.01);
+                for cx in min.x..=max.x {
+                    for cy in min.y..=max.y {
+                        grid.block(IVec2::new(cx, cy));
+                    }
+                }
+            }
+        }
+        grid.changed = previous.symmetric_difference(&grid.blocked).copied().collect();
+    }
+}
+
+/// Cost of a step between neighboring cells when nothing is in the way.
+fn base_cost(step: IVec2) -> i32 {
+    if step.x == 0 || step.y == 0 { 10 } else { 14 }
+}
+
+/// The route a monster takes around obstacles to reach the player, refreshed
+/// at the start of every beat.
+#[derive(Component, Debug, Default)]
+pub struct NavPath {
+    /// Remaining waypoints, the next one last
+    waypoints: Vec<Vec2>,
+}
+
+impl NavPath {
+    /// Where to head next, if the path has anywhere left to go.
+    pub fn next(&self) -> Option<Vec2> {
+        self.waypoints.last().copied()
+    }
+
+    fn update(
+        grid: Res<NavGrid>,
+        mut agents: Query<(&Transform, &mut NavPath)>,
+        character: Query<&Transform, With<Character>>,
+        song: Res<SongPlayback>,
+    ) {
+        let goal = character.single().translation.truncate();
+        for (transform, mut path) in agents.iter_mut() {
+            if !song.bpm_timer.just_finished() && !path.is_added() {
+                continue;
+            }
+            let mut waypoints = grid.waypoints(transform.translation.truncate(), goal).unwrap_or_default();
+            waypoints.reverse();
+            path.waypoints = waypoints;
+        }
+    }
+
+    fn advance(grid: Res<NavGrid>, mut agents: Query<(&Transform, &mut NavPath)>) {
+        for (transform, mut path) in agents.iter_mut() {
+            let position = transform.translation.truncate();
+            while path.waypoints.len() > 1 && path.next().is_some_and(|next| next.distance(position) < grid.cell_size * 0.5) {
+                path.waypoints.pop();
+            }
+        }
+    }
+}
+
+/// Distances to the player over every cell within [`FLOW_RADIUS`] of them,
+/// shared by all [`FlowFollower`]s. Every distance changes with the goal, so
+/// the field is recomputed in full when the player moves to another cell;
+/// terrain changes only repair the cells around them.
+#[derive(Resource, Debug, Default)]
+pub struct FlowField {
+    goal: Option<IVec2>,
+    origin: IVec2,
+    size: i32,
+    costs: Vec<i32>,
+}
+
+impl FlowField {
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let local = cell - self.origin;
+        (local.x >= 0 && local.y >= 0 && local.x < self.size && local.y < self.size)
+            .then(|| (local.y * self.size + local.x) as usize)
+    }
+
+    fn cost(&self, cell: IVec2) -> Option<i32> {
+        self.index(cell).map(|i| self.costs[i]).filter(|&cost| cost < i32::MAX)
+    }
+
+    /// Dijkstra outwards from `goal` over the cells around it.
+    pub fn compute(&mut self, grid: &NavGrid, goal: IVec2) {
+        self.goal = Some(goal);
+        self.origin = goal - IVec2::splat(FLOW_RADIUS);
+        self.size = FLOW_RADIUS * 2 + 1;
+        self.costs.clear();
+        self.costs.resize((self.size * self.size) as usize, i32::MAX);
+        if grid.is_blocked(goal) {
+            return;
+        }
+
+        let goal_index = self.index(goal).unwrap();
+        self.costs[goal_index] = 0;
+        self.spread(grid, BinaryHeap::from([Reverse((0, goal.x, goal.y))]));
+    }
+
+    /// Brings the field up to date after the `changed` cells were blocked or
+    /// cleared, searching again only from the cells whose distances went
+    /// through them.
+    pub fn repair(&mut self, grid: &NavGrid, changed: &HashSet<IVec2>) {
+        let Some(goal) = self.goal else {
+            return;
+        };
+        if changed.contains(&goal) {
+            self.compute(grid, goal);
+            return;
+        }
+
+        // A changed cell can only open or close steps between the cells
+        // around it, by being one of them or the corner a step cuts
+        let mut stale: Vec<IVec2> = Vec::new();
+        for &cell in changed {
+            let around = move || std::iter::once(cell).chain(NEIGHBORS.iter().map(move |&step| cell + step));
+            for near in around() {
+                let Some(cost) = self.cost(near) else {
+                    continue;
+                };
+                if grid.is_blocked(near) || around().any(|from| self.reached_from(near, cost, from)) {
+                    stale.push(near);
+                }
+            }
+        }
+
+        // Everything whose distance went through a stale cell is stale too
+        let mut invalid: HashSet<IVec2> = stale.iter().copied().collect();
+        while let Some(cell) = stale.pop() {
+            for step in NEIGHBORS {
+                let next = cell + step;
+                let Some(cost) = self.cost(next) else {
+                    continue;
+                };
+                if !invalid.contains(&next) && self.reached_from(next, cost, cell) {
+                    invalid.insert(next);
+                    stale.push(next);
+                }
+            }
+        }
+        for &cell in &invalid {
+            let i = self.index(cell).unwrap();
+            self.costs[i] = i32::MAX;
+        }
+
+        // Search again from the cells left around the gaps
+        let mut open = BinaryHeap::new();
+        for &cell in invalid.iter().chain(changed) {
+            for step in NEIGHBORS {
+                if let Some(cost) = self.cost(cell + step) {
+                    open.push(Reverse((cost, cell.x + step.x, cell.y + step.y)));
+                }
+            }
+        }
+        self.spread(grid, open);
+    }
+
+    /// Whether `cell` at `cost` could have been reached in one step from
+    /// its neighbor `from`.
+    fn reached_from(&self, cell: IVec2, cost: i32, from: IVec2) -> bool {
+        let step = cell - from;
+        step != IVec2::ZERO
+            && step.abs().max_element() == 1
+            && self.cost(from).is_some_and(|from_cost| from_cost + base_cost(step) == cost)
+    }
+
+    /// Lowers the costs of the cells around those in `open` for as long as
+    /// going through them is shorter.
+    fn spread(&mut self, grid: &NavGrid, mut open: BinaryHeap<Reverse<(i32, i32, i32)>>) {
+        while let Some(Reverse((cost, x, y))) = open.pop() {
+            let cell = IVec2::new(x, y);
+            if cost > self.costs[self.index(cell).unwrap()] {
+                continue;
+            }
+            for step in NEIGHBORS {
+                // Moves are symmetric, so the cost of stepping back towards
+                // `cell` from its neighbor is the same
+                let Some(step_cost) = grid.step_cost(cell, step) else {
+                    continue;
+                };
+                let Some(next) = self.index(cell + step) else {
+                    continue;
+                };
+                if cost + step_cost < self.costs[next] {
+                    self.costs[next] = cost + step_cost;
+                    open.push(Reverse((cost + step_cost, cell.x + step.x, cell.y + step.y)));
+                }
+            }
+        }
+    }
+
+    /// The center of the neighboring cell closest to the goal, `None` when
+    /// `position` is in the goal cell or outside the field.
+    pub fn next(&self, grid: &NavGrid, position: Vec2) -> Option<Vec2> {
+        let cell = grid.cell(position);
+        if Some(cell) == self.goal {
+            return None;
+        }
+        let here = self.cost(cell).unwrap_or(i32::MAX);
+        NEIGHBORS.iter()
+            .filter(|&&step| grid.step_cost(cell, step).is_some())
+            .filter_map(|&step| Some((self.cost(cell + step)?, cell + step)))
+            .filter(|&(cost, _)| cost < here)
+            .min_by_key(|&(cost, _)| cost)
+            .map(|(_, next)| grid.center(next))
+    }
+
+    fn update(
+        mut field: ResMut<FlowField>,
+        grid: Res<NavGrid>,
+        character: Query<&Transform, With<Character>>,
+    ) {
+        let goal = grid.cell(character.single().translation.truncate());
+        if field.goal != Some(goal) {
+            field.compute(&grid, goal);
+        } else if grid.is_changed() {
+            field.repair(&grid, &grid.changed);
+        }
+    }
+}
+
+/// Follows the shared [`FlowField`] to the player. Cheaper than a
+/// [`NavPath`] when many monsters chase at once.
+#[derive(Component, Debug, Default)]
+pub struct FlowFollower {
+    next: Option<Vec2>,
+}
+
+impl FlowFollower {
+    /// Where to head next, `None` to head straight for the player.
+    pub fn next(&self) -> Option<Vec2> {
+        self.next
+    }
+
+    fn sample(
+        field: Res<FlowField>,
+        grid: Res<NavGrid>,
+        mut followers: Query<(&Transform, &mut FlowFollower)>,
+    ) {
+        for (transform, mut follower) in followers.iter_mut() {
+            follower.next = if grid.blocked.is_empty() {
+                None
+            } else {
+                field.next(&grid, transform.translation.truncate())
+            };
+        }
+    }
+}
+
+/// Whichever of [`NavPath`] and [`FlowFollower`] an entity steers by.
+#[derive(WorldQuery)]
+pub struct Navigation {
+    path: Option<&'static NavPath>,
+    flow: Option<&'static FlowFollower>,
+}
+
+impl NavigationItem<'_> {
+    /// Where to head next, `None` to head straight for the player.
+    pub fn next(&self) -> Option<Vec2> {
+        self.path.and_then(NavPath::next).or_else(|| self.flow.and_then(FlowFollower::next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// A wall between a crowd of agents and the goal.
+    fn walled_grid() -> NavGrid {
+        let mut grid = NavGrid::new(16.0);
+        for y in -20..=20 {
+            grid.block(IVec2::new(0, y));
+        }
+        grid
+    }
+
+    fn agents() -> Vec<Vec2> {
+        (0..1000).map(|i| Vec2::new(-24.0 - (i % 40) as f32 * 16.0, ((i / 40) as f32 - 12.0) * 16.0)).collect()
+    }
+
+    #[test]
+    fn path_in_the_open_is_straight() {
+        let grid = NavGrid::new(16.0);
+        let path = grid.find_path(IVec2::ZERO, IVec2::new(3, 3)).unwrap();
+        assert_eq!(path, vec![IVec2::ZERO, IVec2::ONE, IVec2::splat(2), IVec2::splat(3)]);
+        assert_eq!(grid.find_path(IVec2::ONE, IVec2::ONE), Some(vec![IVec2::ONE]));
+    }
+
+    #[test]
+    fn path_never_cuts_a_blocked_corner() {
+        let mut grid = NavGrid::new(16.0);
+        grid.block(IVec2::new(1, 0));
+        let path = grid.find_path(IVec2::ZERO, IVec2::ONE).unwrap();
+        assert_eq!(path, vec![IVec2::ZERO, IVec2::Y, IVec2::ONE]);
+    }
+
+    #[test]
+    fn no_path_to_blocked_or_walled_in_cells() {
+        let mut grid = NavGrid::new(16.0);
+        grid.block(IVec2::new(5, 0));
+        assert_eq!(grid.find_path(IVec2::ZERO, IVec2::new(5, 0)), None);
+
+        for step in NEIGHBORS {
+            grid.block(IVec2::new(10, 0) + step);
+        }
+        assert_eq!(grid.find_path(IVec2::ZERO, IVec2::new(10, 0)), None);
+    }
+
+    #[test]
+    fn waypoints_end_at_the_goal_itself() {
+        let mut grid = NavGrid::new(16.0);
+        for y in -2..=2 {
+            grid.block(IVec2::new(0, y));
+        }
+        let goal = Vec2::new(40.0, 5.0);
+        let waypoints = grid.waypoints(Vec2::new(-40.0, 5.0), goal).unwrap();
+        assert_eq!(waypoints.last(), Some(&goal));
+        assert!(waypoints.iter().all(|&point| !grid.is_blocked(grid.cell(point))));
+        assert_eq!(grid.waypoints(Vec2::new(-40.0, 100.0), Vec2::new(40.0, 100.0)), Some(vec![Vec2::new(40.0, 100.0)]));
+    }
+
+    #[test]
+    fn path_goes_around_the_wall() {
+        let grid = walled_grid();
+        let from = Vec2::new(-200.0, 0.0);
+        let goal = Vec2::new(200.0, 0.0);
+        let waypoints = grid.waypoints(from, goal).unwrap();
+        assert!(waypoints.len() > 1);
+        assert_eq!(waypoints.last(), Some(&goal));
+        let mut position = from;
+        for &waypoint in &waypoints {
+            assert!(grid.line_of_sight(position, waypoint), "{position} to {waypoint} crosses the wall");
+            position = waypoint;
+        }
+        assert_eq!(grid.waypoints(Vec2::new(100.0, 0.0), goal), Some(vec![goal]));
+    }
+
+    #[test]
+    fn flow_field_leads_every_agent_to_the_goal() {
+        let grid = walled_grid();
+        let goal = IVec2::new(12, 0);
+        let mut field = FlowField::default();
+        field.compute(&grid, goal);
+        for start in agents() {
+            let mut position = start;
+            for _ in 0..200 {
+                match field.next(&grid, position) {
+                    Some(next) => position = next,
+                    None => break,
+                }
+            }
+            assert_eq!(grid.cell(position), goal, "agent from {start} got stuck at {position}");
+        }
+    }
+
+    #[test]
+    fn repaired_flow_field_matches_a_fresh_one() {
+        let mut grid = walled_grid();
+        let goal = IVec2::new(12, 0);
+        let mut field = FlowField::default();
+        field.compute(&grid, goal);
+
+        // Open a gap in the wall and put up a new one closer to the goal
+        let mut changed = HashSet::default();
+        for y in -2..=2 {
+            grid.blocked.remove(&IVec2::new(0, y));
+            changed.insert(IVec2::new(0, y));
+        }
+        for y in -6..=6 {
+            grid.block(IVec2::new(6, y));
+            changed.insert(IVec2::new(6, y));
+        }
+        field.repair(&grid, &changed);
+
+        let mut fresh = FlowField::default();
+        fresh.compute(&grid, goal);
+        assert_eq!(field.costs, fresh.costs);
+
+        // And back again
+        let mut changed = HashSet::default();
+        for y in -6..=6 {
+            grid.blocked.remove(&IVec2::new(6, y));
+            changed.insert(IVec2::new(6, y));
+        }
+        grid.block(IVec2::new(11, 1));
+        changed.insert(IVec2::new(11, 1));
+        field.repair(&grid, &changed);
+        fresh.compute(&grid, goal);
+        assert_eq!(field.costs, fresh.costs);
+    }
+
+    /// Times A* for every agent against one shared flow field. Not a
+    /// correctness check; run it with
+    /// `cargo test --release -- --ignored --nocapture flow_field_benchmark`.
+    #[test]
+    #[ignore]
+    fn flow_field_benchmark() {
+        let mut grid = walled_grid();
+        let goal = Vec2::new(200.0, 0.0);
+        let agents = agents();
+        assert_eq!(agents.len(), 1000);
+
+        let start = Instant::now();
+        for &agent in &agents {
+            assert!(grid.waypoints(agent, goal).is_some());
+        }
+        let direct = start.elapsed();
+
+        let start = Instant::now();
+        let mut field = FlowField::default();
+        field.compute(&grid, grid.cell(goal));
+        for &agent in &agents {
+            assert!(field.next(&grid, agent).is_some());
+        }
+        let flow = start.elapsed();
+
+        let changed: HashSet<IVec2> = (-20..=20).map(|y| IVec2::new(4, y)).collect();
+        for &cell in &changed {
+            grid.block(cell);
+        }
+        let start = Instant::now();
+        field.repair(&grid, &changed);
+        let repair = start.elapsed();
+
+        println!(
+            "{} agents: A* {direct:?}, flow field {flow:?} ({:.1}x faster), repair after a new wall {repair:?}",
+            agents.len(),
+            direct.as_secs_f64() / flow.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    beat::{Beat, BeatScale},
+    character::Character,
+    collision,
+    combat::{Health, Score},
+    terrain::RegisterLdtkEntity,
+    tween::{Curve, Ease, Tween, TweenType},
+};
+
+pub struct PickupPlugin;
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_ldtk_entity("Pickup", |commands, instance| {
+                let amount = instance.field("amount").and_then(|value| value.as_f64()).unwrap_or(1.0) as f32;
+                let kind = match instance.field("kind").and_then(|value| value.as_str()) {
+                    Some("Points") => PickupKind::Points(amount as u32),
+                    _ => PickupKind::Health(amount),
+                };
+                commands.insert((
+                    Sprite { color: kind.color(), custom_size: Some(Vec2::splat(10.0)), ..default() },
+                    Handle::<Image>::default(),
+                    BeatScale { beat: Beat { tween: Tween { ttype: TweenType::Triangle, a: 1.0, b: 1.3, mid: 0.2, ease: Ease::Out(Curve::Quad), ..default() }, freq: 1, on_beat: 0 } },
+                    Collider::ball(8.0),
+                    Sensor,
+                    CollisionGroups::new(collision::TRIGGER, collision::PLAYER),
+                    ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_FIXED,
+                    ActiveEvents::COLLISION_EVENTS,
+                    Pickup { kind },
+                ));
+            })
+            .add_systems(Update, Pickup::collect);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PickupKind {
+    /// Heals the player, up to their max health
+    Health(f32),
+    Points(u32),
+}
+
+impl PickupKind {
+    fn color(self) -> Color {
+        match self {
+            PickupKind::Health(_) => Color::LIME_GREEN,
+            PickupKind::Points(_) => Color::GOLD,
+        }
+    }
+}
+
+/// Used up by the player walking into it.
+#[derive(Component, Debug)]
+pub struct Pickup {
+    pub kind: PickupKind,
+}
+
+impl Pickup {
+    fn collect(
+        mut commands: Commands,
+        mut collisions: EventReader<CollisionEvent>,
+        pickups: Query<&Pickup>,
+        mut character: Query<&mut Health, With<Character>>,
+        mut score: ResMut<Score>,
+    ) {
+        for collision in collisions.read() {
+            let CollisionEvent::Started(a, b, _) = *collision else {
+                continue;
+            };
+            for (pickup_entity, other) in [(a, b), (b, a)] {
+                let (Ok(pickup), Ok(mut health)) = (pickups.get(pickup_entity), character.get_mut(other)) else {
+                    continue;
+                };
+                match pickup.kind {
+                    PickupKind::Health(amount) => health.current = (health.current + amount).min(health.max),
+                    PickupKind::Points(points) => score.0 += points,
+                }
+                commands.entity(pickup_entity).despawn_recursive();
+            }
+        }
+    }
+}
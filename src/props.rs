@@ -0,0 +1,255 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    bullet::EnemyBullet,
+    character::Character,
+    combat::{CombatSet, DamageEvent, Health, Killed},
+    ldtk::TilesetDef,
+};
+
+pub struct PropPlugin;
+impl Plugin for PropPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_event::<Exploded>()
+            .add_systems(Update, Prop::shot.before(CombatSet::Damage))
+            .add_systems(Update, (Prop::damage, Prop::destroy).after(CombatSet::Damage).before(CombatSet::Despawn));
+    }
+}
+
+/// Splits a collision layer into props that can be shot apart, see
+/// [`CollisionRule::props`](crate::terrain::CollisionRule::props).
+#[derive(Debug, Clone)]
+pub struct PropRule {
+    pub health: f32,
+    pub shape: PropShape,
+    pub explosion: Option<PropExplosion>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PropShape {
+    /// Every tile is a prop of its own, like the pieces of a fence
+    Tile,
+    /// Tiles touching each other make up one prop, like a vehicle
+    Connected,
+}
+
+impl PropShape {
+    /// Groups the cells of a row-major grid that have a tile into props.
+    pub fn props(self, tiles: &[Option<u32>], width: usize) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; tiles.len()];
+        let mut props = Vec::new();
+        for start in 0..tiles.len() {
+            if seen[start] || tiles[start].is_none() {
+                continue;
+            }
+            seen[start] = true;
+            let mut prop = vec![start];
+            if let PropShape::Connected = self {
+                let mut next = 0;
+                while let Some(&i) = prop.get(next) {
+                    next += 1;
+                    let x = i % width;
+                    let neighbours = [
+                        (x > 0).then(|| i - 1),
+                        (x + 1 < width).then_some(i + 1),
+                        i.checked_sub(width),
+                        Some(i + width).filter(|&n| n < tiles.len()),
+                    ];
+                    for n in neighbours.into_iter().flatten() {
+                        if !seen[n] && tiles[n].is_some() {
+                            seen[n] = true;
+                            prop.push(n);
+                        }
+                    }
+                }
+            }
+            props.push(prop);
+        }
+        props
+    }
+}
+
+/// Damage dealt to everything with health but the player within `radius`
+/// of a destroyed prop.
+#[derive(Debug, Clone)]
+pub struct PropExplosion {
+    pub radius: f32,
+    pub damage: f32,
+}
+
+/// Sent when a destroyed prop explodes.
+#[derive(Event, Debug)]
+pub struct Exploded {
+    pub position: Vec2,
+    pub radius: f32,
+}
+
+/// Tiles a tileset swaps in for damaged props, from tile custom data like
+/// `{"damaged": 37}`.
+pub fn damaged_tiles(tileset: &TilesetDef) -> HashMap<u32, u32> {
+    #[derive(Deserialize)]
+    struct TileDamage {
+        damaged: Option<u32>,
+    }
+
+    tileset.custom_data
+        .iter()
+        .filter_map(|tile| {
+            let damage: TileDamage = serde_json::from_str(&tile.data).ok()?;
+            Some((tile.tile_id, damage.damaged?))
+        })
+        .collect()
+}
+
+/// Part of a destructible layer with its own collider and health. Its tiles
+/// look damaged below half health and go when it is destroyed.
+#[derive(Component, Debug)]
+pub struct Prop {
+    /// The layer's tilemap, unless it isn't drawn
+    pub tilemap: Option<Entity>,
+    pub tiles: Vec<PropTile>,
+    pub explosion: Option<PropExplosion>,
+    damaged: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PropTile {
+    pub position: TilePos,
+    /// The tile's damaged look, darkening it if there is none
+    pub damaged: Option<u32>,
+}
+
+impl Prop {
+    pub fn new(tilemap: Option<Entity>, tiles: Vec<PropTile>, explosion: Option<PropExplosion>) -> Self {
+        Self { tilemap, tiles, explosion, damaged: false }
+    }
+
+    /// Player attacks already damage anything with health; enemy bullets only
+    /// go for the player, so they are handled here.
+    fn shot(
+        mut collisions: EventReader<CollisionEvent>,
+        mut damage: EventWriter<DamageEvent>,
+        bullets: Query<&EnemyBullet>,
+        props: Query<(), With<Prop>>,
+    ) {
+        for collision in collisions.read() {
+            let CollisionEvent::Started(a, b, _) = *collision else {
+                continue;
+            };
+            for (bullet_entity, other) in [(a, b), (b, a)] {
+                if let (Ok(bullet), true) = (bullets.get(bullet_entity), props.contains(other)) {
+                    damage.send(DamageEvent { target: other, amount: bullet.damage, source: Some(bullet_entity) });
+                }
+            }
+        }
+    }
+
+    fn damage(
+        mut props: Query<(&mut Prop, &Health), Changed<Health>>,
+        tilemaps: Query<&TileStorage>,
+        mut tiles: Query<(&mut TileTextureIndex, &mut TileColor)>,
+    ) {
+        for (mut prop, health) in props.iter_mut() {
+            if prop.damaged || health.fraction() > 0.5 {
+                continue;
+            }
+            prop.damaged = true;
+            let Some(storage) = prop.tilemap.and_then(|tilemap| tilemaps.get(tilemap).ok()) else {
+                continue;
+            };
+            for tile in &prop.tiles {
+                let Some(Ok((mut index, mut color))) = storage.get(&tile.position).map(|entity| tiles.get_mut(entity)) else {
+                    continue;
+                };
+                match tile.damaged {
+                    Some(damaged) => index.0 = damaged,
+                    None => color.0 = Color::rgba(0.5, 0.45, 0.45, color.0.a()),
+                }
+            }
+        }
+    }
+
+    /// Clears the tiles of destroyed props and sets off their explosions. The
+    /// death pipeline despawns the prop itself, which takes its collider out
+    /// of the nav grid.
+    fn destroy(
+        mut commands: Commands,
+        mut killed: EventReader<Killed>,
+        mut damage: EventWriter<DamageEvent>,
+        mut exploded: EventWriter<Exploded>,
+        props: Query<(&Prop, &GlobalTransform)>,
+        mut tilemaps: Query<&mut TileStorage>,
+        targets: Query<(Entity, &GlobalTransform), (With<Health>, Without<Character>)>,
+    ) {
+        for event in killed.read() {
+            let Ok((prop, transform)) = props.get(event.entity) else {
+                continue;
+            };
+            if let Some(mut storage) = prop.tilemap.and_then(|tilemap| tilemaps.get_mut(tilemap).ok()) {
+                for tile in &prop.tiles {
+                    if let Some(entity) = storage.get(&tile.position) {
+                        commands.entity(entity).despawn();
+                        storage.remove(&tile.position);
+                    }
+                }
+            }
+
+            let Some(explosion) = &prop.explosion else {
+                continue;
+            };
+            let center = transform.translation().truncate();
+            exploded.send(Exploded { position: center, radius: explosion.radius });
+            for (target, target_transform) in targets.iter() {
+                if target != event.entity && target_transform.translation().truncate().distance(center) <= explosion.radius {
+                    damage.send(DamageEvent { target, amount: explosion.damage, source: Some(event.entity) });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tiles of a row-major grid from rows of `#` and `.`.
+    fn tiles(rows: &[&str]) -> (Vec<Option<u32>>, usize) {
+        (rows.iter().flat_map(|row| row.chars().map(|c| (c == '#').then_some(1))).collect(), rows[0].len())
+    }
+
+    #[test]
+    fn touching_tiles_make_one_prop() {
+        let (tiles, width) = tiles(&[
+            "##..#",
+            ".#..#",
+            "...##",
+            "#....",
+        ]);
+        let mut props = PropShape::Connected.props(&tiles, width);
+        for prop in &mut props {
+            prop.sort();
+        }
+        assert_eq!(props, vec![vec![0, 1, 6], vec![4, 9, 13, 14], vec![15]]);
+    }
+
+    #[test]
+    fn diagonal_tiles_and_row_ends_stay_apart() {
+        let (tiles, width) = tiles(&[
+            "#.#",
+            ".#.",
+            "..#",
+            "#..",
+        ]);
+        assert_eq!(PropShape::Connected.props(&tiles, width).len(), 5);
+    }
+
+    #[test]
+    fn tile_props_are_single_tiles() {
+        let (tiles, width) = tiles(&["##.", ".##"]);
+        assert_eq!(PropShape::Tile.props(&tiles, width), vec![vec![0], vec![1], vec![4], vec![5]]);
+    }
+}
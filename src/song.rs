@@ -1,28 +1,121 @@
 use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    beat::BeatSet,
+    character::Character,
+    collision,
+    terrain::RegisterLdtkEntity,
+};
 
 pub struct SongPlugin;
 impl Plugin for SongPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
+            .register_ldtk_entity("SongTrigger", |commands, instance| {
+                let Some(marker) = instance.field("marker").and_then(|value| value.as_str()) else {
+                    warn!("SongTrigger {} has no marker", instance.iid);
+                    return;
+                };
+                let half = instance.size() / 2.0;
+                commands.insert((
+                    SongTrigger { marker: marker.into() },
+                    Collider::cuboid(half.x, half.y),
+                    Sensor,
+                    CollisionGroups::new(collision::TRIGGER, collision::PLAYER),
+                    ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_FIXED,
+                    ActiveEvents::COLLISION_EVENTS,
+                ));
+            })
+            .add_event::<SongEnded>()
             .add_systems(Startup, load_music)
-            .add_systems(Update, tick_song);
+            .add_systems(Update, tick_song.before(BeatSet::Prepare))
+            .add_systems(Update, (SongTrigger::system, end_song));
     }
 }
 
+pub const BEATS_PER_BAR: usize = 4;
+
 #[derive(Resource)]
 pub struct SongPlayback {
     pub bpm_timer: Timer,
     pub beat_count: usize,
+    pub markers: Vec<SongMarker>,
+}
+
+impl SongPlayback {
+    /// Whether the song has played up to the marker called `name`.
+    pub fn reached(&self, name: &str) -> bool {
+        self.markers.iter().any(|m| m.name == name && self.beat_count >= m.beat)
+    }
+}
+
+/// Sent once the music has played to the end.
+#[derive(Event, Debug)]
+pub struct SongEnded;
+
+#[derive(Component)]
+struct Music;
+
+/// A named beat in the song, used to line up gameplay with song sections.
+#[derive(Debug, Clone)]
+pub struct SongMarker {
+    pub name: String,
+    pub beat: usize,
+}
+
+impl SongMarker {
+    pub fn new(name: impl Into<String>, beat: usize) -> Self {
+        Self { name: name.into(), beat }
+    }
+}
+
+/// Reaches song marker `marker` early when the player walks in, for
+/// anything waiting on it like the boss.
+#[derive(Component, Debug)]
+pub struct SongTrigger {
+    pub marker: String,
+}
+
+impl SongTrigger {
+    fn system(
+        mut collisions: EventReader<CollisionEvent>,
+        triggers: Query<&SongTrigger>,
+        character: Query<(), With<Character>>,
+        mut song: ResMut<SongPlayback>,
+    ) {
+        for collision in collisions.read() {
+            let CollisionEvent::Started(a, b, _) = *collision else {
+                continue;
+            };
+            for (trigger, other) in [(a, b), (b, a)] {
+                let (Ok(trigger), true) = (triggers.get(trigger), character.contains(other)) else {
+                    continue;
+                };
+                if !song.reached(&trigger.marker) {
+                    let beat = song.beat_count;
+                    song.markers.push(SongMarker::new(trigger.marker.clone(), beat));
+                }
+            }
+        }
+    }
 }
 
 fn load_music(asset_server: Res<AssetServer>, mut commands: Commands) {
-    commands.spawn(AudioBundle {
-        source: asset_server.load("music/Wish You Were Here.mp3"),
-        ..default()
-    });
+    commands.spawn((
+        AudioBundle {
+            source: asset_server.load("music/Wish You Were Here.mp3"),
+            ..default()
+        },
+        Music,
+    ));
     commands.insert_resource(SongPlayback {
         bpm_timer: Timer::from_seconds(0.48, TimerMode::Repeating),
         beat_count: 0,
+        markers: vec![
+            SongMarker::new("boss", 64),
+            SongMarker::new("boss-rage", 128),
+        ],
     });
 }
 
@@ -35,3 +128,18 @@ fn tick_song(
         song.beat_count += 1;
     }
 }
+
+fn end_song(
+    music: Query<&AudioSink, With<Music>>,
+    mut ended: EventWriter<SongEnded>,
+    mut sent: Local<bool>,
+) {
+    // The sink only shows up once the music starts playing
+    let Ok(sink) = music.get_single() else {
+        return;
+    };
+    if sink.empty() && !*sent {
+        *sent = true;
+        ended.send(SongEnded);
+    }
+}
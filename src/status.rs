@@ -0,0 +1,208 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    combat::{CombatSet, DamageEvent},
+    song::SongPlayback,
+};
+
+pub struct StatusPlugin;
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .add_event::<ApplyStatus>()
+            // Kept clear of the death pipeline so nothing is queued for a
+            // despawned entity
+            .add_systems(Update, (Statuses::tick, StatusIcon::update).chain().before(CombatSet::Damage))
+            .add_systems(Update, (inflict_on_hit, Statuses::apply).chain().after(CombatSet::Damage).before(CombatSet::Despawn));
+    }
+}
+
+/// How many times one effect stacks on the same entity.
+const MAX_STACKS: u32 = 3;
+
+/// Damage a burn stack deals on each beat.
+const BURN_DAMAGE: f32 = 0.5;
+
+/// Speed kept per stack of slow.
+const SLOW_FACTOR: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusKind {
+    /// Moves slower, more so with each stack
+    Slow,
+    /// Takes damage on every beat
+    Burn,
+    /// Can't move or act
+    Freeze,
+    /// Knocked off the beat: moves, but skips its beat actions
+    Stun,
+}
+
+impl StatusKind {
+    fn color(self) -> Color {
+        match self {
+            StatusKind::Slow => Color::PURPLE,
+            StatusKind::Burn => Color::ORANGE,
+            StatusKind::Freeze => Color::CYAN,
+            StatusKind::Stun => Color::YELLOW,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub stacks: u32,
+    /// The beat the effect wears off on
+    pub until: usize,
+}
+
+/// Status effects currently on an entity, with durations in beats.
+#[derive(Component, Debug, Default)]
+pub struct Statuses {
+    effects: Vec<StatusEffect>,
+}
+
+impl Statuses {
+    /// Adds a stack of `kind` and extends it to last at least `beats` beats
+    /// past `beat`.
+    pub fn add(&mut self, kind: StatusKind, beats: usize, beat: usize) {
+        let until = beat + beats;
+        match self.effects.iter_mut().find(|e| e.kind == kind) {
+            Some(effect) => {
+                effect.stacks = (effect.stacks + 1).min(MAX_STACKS);
+                effect.until = effect.until.max(until);
+            }
+            None => self.effects.push(StatusEffect { kind, stacks: 1, until }),
+        }
+    }
+
+    pub fn stacks(&self, kind: StatusKind) -> u32 {
+        self.effects.iter().find(|e| e.kind == kind).map_or(0, |e| e.stacks)
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.stacks(kind) > 0
+    }
+
+    /// Whether beat behaviors should skip their actions, like shooting.
+    pub fn suppresses_actions(&self) -> bool {
+        self.has(StatusKind::Freeze) || self.has(StatusKind::Stun)
+    }
+
+    /// Whether movement behaviors should hold still.
+    pub fn suppresses_movement(&self) -> bool {
+        self.has(StatusKind::Freeze)
+    }
+
+    /// What to multiply the speeds of movement behaviors by, see
+    /// [`BeatBehavior::MOVEMENT`](crate::beat::BeatBehavior::MOVEMENT).
+    pub fn speed_scale(&self) -> f32 {
+        SLOW_FACTOR.powi(self.stacks(StatusKind::Slow) as i32)
+    }
+
+    fn apply(
+        mut commands: Commands,
+        mut events: EventReader<ApplyStatus>,
+        mut statuses: Query<&mut Statuses>,
+        song: Res<SongPlayback>,
+    ) {
+        // Inserted once per entity, so several effects landing on the same
+        // frame all stack
+        let mut added: HashMap<Entity, Statuses> = HashMap::default();
+        for event in events.read() {
+            if let Ok(mut statuses) = statuses.get_mut(event.target) {
+                statuses.add(event.kind, event.beats, song.beat_count);
+            } else {
+                added.entry(event.target).or_default().add(event.kind, event.beats, song.beat_count);
+            }
+        }
+        for (target, statuses) in added {
+            if let Some(mut entity) = commands.get_entity(target) {
+                entity.insert(statuses);
+            }
+        }
+    }
+
+    fn tick(
+        mut statuses: Query<(Entity, &mut Statuses)>,
+        mut damage: EventWriter<DamageEvent>,
+        song: Res<SongPlayback>,
+    ) {
+        if !song.bpm_timer.just_finished() {
+            return;
+        }
+        for (entity, mut statuses) in statuses.iter_mut() {
+            // Only flagged as changed when something wore off, so the icons
+            // aren't rebuilt every beat
+            if statuses.effects.iter().any(|e| e.until <= song.beat_count) {
+                statuses.effects.retain(|e| e.until > song.beat_count);
+            }
+            let burn = statuses.stacks(StatusKind::Burn);
+            if burn > 0 {
+                damage.send(DamageEvent { target: entity, amount: BURN_DAMAGE * burn as f32, source: None });
+            }
+        }
+    }
+}
+
+/// Asks for a status effect to be put on `target`.
+#[derive(Event, Debug)]
+pub struct ApplyStatus {
+    pub target: Entity,
+    pub kind: StatusKind,
+    pub beats: usize,
+}
+
+/// Attacks with this put a status effect on whatever they damage.
+#[derive(Component, Debug, Clone)]
+pub struct Inflicts {
+    pub kind: StatusKind,
+    pub beats: usize,
+}
+
+fn inflict_on_hit(
+    mut damage: EventReader<DamageEvent>,
+    mut apply: EventWriter<ApplyStatus>,
+    inflicts: Query<&Inflicts>,
+) {
+    for event in damage.read() {
+        if let Some(inflicts) = event.source.and_then(|source| inflicts.get(source).ok()) {
+            apply.send(ApplyStatus { target: event.target, kind: inflicts.kind, beats: inflicts.beats });
+        }
+    }
+}
+
+/// A pip above an entity for each status effect on it.
+#[derive(Component, Debug)]
+struct StatusIcon;
+
+impl StatusIcon {
+    fn update(
+        mut commands: Commands,
+        statuses: Query<(Entity, &Statuses, Option<&Children>), Changed<Statuses>>,
+        icons: Query<(), With<StatusIcon>>,
+    ) {
+        for (entity, statuses, children) in statuses.iter() {
+            for &child in children.into_iter().flatten() {
+                if icons.contains(child) {
+                    commands.entity(child).despawn_recursive();
+                }
+            }
+            commands.entity(entity).with_children(|builder| {
+                let count = statuses.effects.len() as f32;
+                for (i, effect) in statuses.effects.iter().enumerate() {
+                    let size = 3.0 + effect.stacks as f32;
+                    builder.spawn((
+                        SpriteBundle {
+                            sprite: Sprite { color: effect.kind.color(), custom_size: Some(Vec2::splat(size)), ..default() },
+                            transform: Transform::from_xyz((i as f32 - (count - 1.0) / 2.0) * 8.0, 20.0, 0.5),
+                            ..default()
+                        },
+                        StatusIcon,
+                    ));
+                }
+            });
+        }
+    }
+}
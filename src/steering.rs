@@ -0,0 +1,254 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::Velocity;
+use serde::{Deserialize, Serialize};
+
+use crate::{beat::BeatSet, status::{StatusKind, Statuses}};
+
+pub struct SteeringPlugin;
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_type::<Flocking>()
+            .init_resource::<SpatialHash>()
+            .add_systems(Update, (Steering::clear, SpatialHash::rebuild).in_set(BeatSet::Prepare))
+            .add_systems(Update, Flocking::system.in_set(BeatSet::Behave))
+            .add_systems(Update, Steering::resolve.in_set(BeatSet::Apply));
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SteeringKind {
+    Seek,
+    Flee,
+    Orbit,
+    Separation,
+    Alignment,
+    Cohesion,
+    Dash,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct SteeringForce {
+    kind: SteeringKind,
+    priority: u8,
+    weight: f32,
+    linvel: Vec2,
+}
+
+/// The desired velocities movement behaviors want this frame.
+///
+/// Behaviors add forces while in [`BeatSet::Behave`], and in [`BeatSet::Apply`]
+/// the forces of the highest priority present are blended by weight into the
+/// Rapier [`Velocity`]. An entity without any forces comes to a stop.
+#[derive(Component, Debug, Default)]
+pub struct Steering {
+    forces: Vec<SteeringForce>,
+}
+
+impl Steering {
+    pub fn add(&mut self, kind: SteeringKind, priority: u8, weight: f32, linvel: Vec2) {
+        self.forces.push(SteeringForce { kind, priority, weight, linvel });
+    }
+
+    /// The velocity the forces come to.
+    pub fn combined(&mut self) -> Vec2 {
+        // Sorting makes the result independent of the order behaviors ran in
+        self.forces.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.kind.cmp(&b.kind)));
+        let Some(top) = self.forces.first().map(|f| f.priority) else {
+            return Vec2::ZERO;
+        };
+        self.forces.iter()
+            .take_while(|f| f.priority == top)
+            .fold(Vec2::ZERO, |sum, f| sum + f.linvel * f.weight)
+    }
+
+    fn clear(mut steering: Query<&mut Steering>) {
+        for mut steering in steering.iter_mut() {
+            steering.forces.clear();
+        }
+    }
+
+    fn resolve(mut movers: Query<(&mut Steering, &mut Velocity, Option<&SpeedScale>, Option<&Statuses>)>) {
+        for (mut steering, mut velocity, scale, statuses) in movers.iter_mut() {
+            // Slow already lowered the speeds the beat behaviors steer at
+            let frozen = statuses.is_some_and(|statuses| statuses.has(StatusKind::Freeze));
+            let scale = if frozen { 0.0 } else { scale.map_or(1.0, |s| s.0) };
+            velocity.linvel = steering.combined() * scale;
+        }
+    }
+}
+
+/// Multiplies the speed an entity's [`Steering`] resolves to.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SpeedScale(pub f32);
+
+pub fn seek(from: Vec2, to: Vec2, speed: f32) -> Vec2 {
+    (to - from).normalize_or_zero() * speed
+}
+
+pub fn flee(from: Vec2, away: Vec2, speed: f32) -> Vec2 {
+    -seek(from, away, speed)
+}
+
+pub fn orbit(from: Vec2, center: Vec2, speed: f32, clockwise: bool) -> Vec2 {
+    let tangent = (from - center).normalize_or_zero().perp() * speed;
+    if clockwise { -tangent } else { tangent }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbor {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+/// Buckets flocking entities into square cells so neighbor lookups only have
+/// to look at the cells overlapping the search radius.
+#[derive(Resource, Debug)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<Neighbor>>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(64.0)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self { cell_size, cells: HashMap::default() }
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Drops every bucket, so cells the flock has left don't pile up.
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn insert(&mut self, neighbor: Neighbor) {
+        let cell = self.cell(neighbor.position);
+        self.cells.entry(cell).or_default().push(neighbor);
+    }
+
+    pub fn neighbors(&self, position: Vec2, radius: f32) -> impl Iterator<Item = &Neighbor> + '_ {
+        let min = self.cell(position - Vec2::splat(radius));
+        let max = self.cell(position + Vec2::splat(radius));
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |n| n.position.distance_squared(position) <= radius * radius)
+    }
+
+    fn rebuild(
+        mut hash: ResMut<SpatialHash>,
+        flock: Query<(Entity, &Transform, &Velocity), With<Flocking>>,
+    ) {
+        hash.clear();
+        for (entity, transform, velocity) in flock.iter() {
+            hash.insert(Neighbor { entity, position: transform.translation.truncate(), velocity: velocity.linvel });
+        }
+    }
+}
+
+/// Boids style steering among monsters within `radius` of each other.
+///
+/// `separation` and `cohesion` are the top speeds (px/s) at which a monster
+/// moves away from crowding neighbors or toward the middle of its group, and
+/// `alignment` is how much of the group's average velocity it adopts.
+#[derive(Component, Debug, Clone, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct Flocking {
+    pub radius: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+}
+
+impl Default for Flocking {
+    fn default() -> Self {
+        Self { radius: 48.0, separation: 60.0, alignment: 0.0, cohesion: 0.0 }
+    }
+}
+
+impl Flocking {
+    fn system(
+        hash: Res<SpatialHash>,
+        mut flock: Query<(Entity, &Transform, &Flocking, &mut Steering)>,
+    ) {
+        for (entity, transform, flocking, mut steering) in flock.iter_mut() {
+            let position = transform.translation.truncate();
+            let mut separation = Vec2::ZERO;
+            let mut velocity_sum = Vec2::ZERO;
+            let mut position_sum = Vec2::ZERO;
+            let mut count = 0;
+            for neighbor in hash.neighbors(position, flocking.radius) {
+                if neighbor.entity == entity {
+                    continue;
+                }
+                let closeness = 1.0 - position.distance(neighbor.position) / flocking.radius;
+                separation += flee(position, neighbor.position, flocking.separation * closeness);
+                velocity_sum += neighbor.velocity;
+                position_sum += neighbor.position;
+                count += 1;
+            }
+            if count == 0 {
+                continue;
+            }
+
+            let count = count as f32;
+            steering.add(SteeringKind::Separation, 0, 1.0, separation);
+            steering.add(SteeringKind::Alignment, 0, flocking.alignment, velocity_sum / count);
+            steering.add(SteeringKind::Cohesion, 0, 1.0, seek(position, position_sum / count, flocking.cohesion));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbor(index: u32, position: Vec2) -> Neighbor {
+        Neighbor { entity: Entity::from_raw(index), position, velocity: Vec2::ZERO }
+    }
+
+    fn found(hash: &SpatialHash, position: Vec2, radius: f32) -> Vec<u32> {
+        let mut found: Vec<u32> = hash.neighbors(position, radius).map(|n| n.entity.index()).collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn neighbors_within_radius_across_cells() {
+        let mut hash = SpatialHash::new(64.0);
+        hash.insert(neighbor(0, Vec2::new(10.0, 10.0)));
+        // Next cell over, in range
+        hash.insert(neighbor(1, Vec2::new(70.0, 10.0)));
+        // Negative cell, in range
+        hash.insert(neighbor(2, Vec2::new(-20.0, -20.0)));
+        // In an overlapping cell but out of range
+        hash.insert(neighbor(3, Vec2::new(100.0, 100.0)));
+        hash.insert(neighbor(4, Vec2::new(1000.0, 0.0)));
+
+        assert_eq!(found(&hash, Vec2::new(10.0, 10.0), 64.0), [0, 1, 2]);
+        assert_eq!(found(&hash, Vec2::new(1000.0, 0.0), 1.0), [4]);
+        assert!(found(&hash, Vec2::new(500.0, 500.0), 32.0).is_empty());
+    }
+
+    #[test]
+    fn clear_forgets_visited_cells() {
+        let mut hash = SpatialHash::new(64.0);
+        for x in 0..100 {
+            hash.insert(neighbor(x, Vec2::new(x as f32 * 64.0, 0.0)));
+        }
+        hash.clear();
+        hash.insert(neighbor(0, Vec2::ZERO));
+        assert_eq!(hash.cells.len(), 1);
+        assert_eq!(found(&hash, Vec2::new(64.0, 0.0), 128.0), [0]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::marker::PhantomData;
+
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    beat::{Beat, BeatContext, BeatScale, BeatSet},
+    character::Character,
+    elite::Tint,
+    nav::Navigation,
+    song::SongPlayback,
+    tween::Tween,
+};
+
+pub struct TelegraphPlugin;
+impl Plugin for TelegraphPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app
+            .register_type::<Telegraph>()
+            .add_systems(Update, clear_telegraphs.in_set(BeatSet::Prepare))
+            .add_systems(Update, Flashing::system.in_set(BeatSet::Apply));
+    }
+}
+
+/// How an entity warns the player, one beat ahead, about its next action.
+#[derive(Component, Clone, Debug, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Serialize, Deserialize)]
+pub enum Telegraph {
+    /// A line from the entity in the direction of the action
+    Line { length: f32, color: Color },
+    /// Blink the entity's sprites
+    Flash(Color),
+    /// Scale the entity over the warning beat
+    WindUp(Tween),
+}
+
+impl Default for Telegraph {
+    fn default() -> Self {
+        Telegraph::Flash(Color::WHITE)
+    }
+}
+
+/// An action that can tell what it is going to do before it happens.
+pub trait Telegraphed: Component + Sized {
+    /// Called at the start of each beat with the number of the beat after it.
+    /// Returns the direction of the action when it fires on `beat` (zero if
+    /// it has none), or `None` when nothing happens then.
+    fn preview(&mut self, beat: usize, position: Vec2, context: &BeatContext) -> Option<Vec2>;
+}
+
+pub struct TelegraphedPlugin<T>(PhantomData<T>);
+impl<T> Default for TelegraphedPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+impl<T: Telegraphed> Plugin for TelegraphedPlugin<T> {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_systems(Update, telegraph::<T>.in_set(BeatSet::Behave));
+    }
+}
+
+#[derive(Component)]
+struct TelegraphLine;
+
+#[derive(Component)]
+struct WindingUp;
+
+#[derive(Component)]
+struct Flashing(Color);
+
+impl Flashing {
+    fn system(
+        flashing: Query<(&Flashing, &Children, Option<&Tint>)>,
+        mut sprites: Query<&mut TextureAtlasSprite>,
+        song: Res<SongPlayback>,
+    ) {
+        let on = (song.bpm_timer.percent() * 4.0) as usize % 2 == 0;
+        for (flash, children, tint) in flashing.iter() {
+            let rest = tint.map_or(Color::WHITE, |t| t.0);
+            let mut iter = sprites.iter_many_mut(children.iter());
+            while let Some(mut sprite) = iter.fetch_next() {
+                sprite.color = if on { flash.0 } else { rest };
+            }
+        }
+    }
+}
+
+fn telegraph<T: Telegraphed>(
+    mut commands: Commands,
+    mut actions: Query<(Entity, &mut T, &GlobalTransform, &Telegraph, Navigation)>,
+    character: Query<&Transform, With<Character>>,
+    song: Res<SongPlayback>,
+) {
+    if !song.bpm_timer.just_finished() {
+        return;
+    }
+    let character = character.single().translation;
+    for (entity, mut action, transform, telegraph, navigation) in actions.iter_mut() {
+        let context = BeatContext::new(character, navigation.next(), song.beat_count);
+        let position = transform.translation().truncate();
+        let Some(direction) = action.preview(song.beat_count + 1, position, &context) else {
+            continue;
+        };
+        match telegraph {
+            Telegraph::Line { length, color } => {
+                if direction == Vec2::ZERO {
+                    continue;
+                }
+                commands.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: *color,
+                            custom_size: Some(Vec2::new(*length, 4.0)),
+                            anchor: Anchor::CenterLeft,
+                            ..default()
+                        },
+                        transform: Transform {
+                            translation: position.extend(0.5),
+                            rotation: Quat::from_rotation_z(direction.y.atan2(direction.x)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    TelegraphLine,
+                ));
+            }
+            Telegraph::Flash(color) => {
+                commands.entity(entity).insert(Flashing(*color));
+            }
+            Telegraph::WindUp(tween) => {
+                commands.entity(entity).insert((
+                    BeatScale { beat: Beat { tween: tween.clone(), freq: 1, on_beat: 0 } },
+                    WindingUp,
+                ));
+            }
+        }
+    }
+}
+
+fn clear_telegraphs(
+    mut commands: Commands,
+    lines: Query<Entity, With<TelegraphLine>>,
+    flashing: Query<(Entity, &Children, Option<&Tint>), With<Flashing>>,
+    mut winding_up: Query<(Entity, &mut Transform), With<WindingUp>>,
+    mut sprites: Query<&mut TextureAtlasSprite>,
+    song: Res<SongPlayback>,
+) {
+    if !song.bpm_timer.just_finished() {
+        return;
+    }
+    for entity in lines.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (entity, children, tint) in flashing.iter() {
+        let mut iter = sprites.iter_many_mut(children.iter());
+        while let Some(mut sprite) = iter.fetch_next() {
+            sprite.color = tint.map_or(Color::WHITE, |t| t.0);
+        }
+        commands.entity(entity).remove::<Flashing>();
+    }
+    for (entity, mut transform) in winding_up.iter_mut() {
+        transform.scale = Vec3::ONE;
+        commands.entity(entity).remove::<(BeatScale, WindingUp)>();
+    }
+}
@@ -1,50 +1,518 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
 use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    beat_terrain::TileBeat,
+    city::{BUILDINGS_LAYER, FENCES_LAYER, VEHICLES_LAYER},
+    collision,
+    combat::Health,
+    ldtk::{EntityInstance, LayerInstance, LdtkLoader, LdtkMap, Level},
+    props::{damaged_tiles, Prop, PropExplosion, PropRule, PropShape, PropTile},
+};
 
 pub struct TerrainPlugin;
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
             .add_plugins(TilemapPlugin)
-            .add_systems(Startup, load_tiles);
+            .init_asset::<LdtkMap>()
+            .init_asset_loader::<LdtkLoader>()
+            .init_resource::<TileCollision>()
+            .init_resource::<LdtkEntities>()
+            .add_event::<LevelSpawned>()
+            .add_systems(Update, (LdtkLevel::spawn, LdtkLevel::unload));
+    }
+}
+
+/// Z of the bottom tile layer; each layer above it goes up by [`LAYER_STEP`],
+/// staying under everything else in the world.
+const LAYER_Z: f32 = -1.0;
+const LAYER_STEP: f32 = 0.01;
+
+/// Which LDtk layers block what, by layer identifier. Layers without a rule
+/// don't collide.
+#[derive(Resource, Debug)]
+pub struct TileCollision {
+    pub layers: HashMap<String, CollisionRule>,
+}
+
+impl Default for TileCollision {
+    fn default() -> Self {
+        let mut layers = HashMap::default();
+        // The buildings in the test map
+        layers.insert("Tiles2".into(), CollisionRule::tiles(Group::ALL));
+        // The rest are layers of generated cities
+        layers.insert(BUILDINGS_LAYER.into(), CollisionRule::tiles(Group::ALL));
+        layers.insert(VEHICLES_LAYER.into(), CollisionRule::tiles(Group::ALL).with_props(PropRule {
+            health: 6.0,
+            shape: PropShape::Connected,
+            explosion: Some(PropExplosion { radius: 48.0, damage: 3.0 }),
+        }));
+        // Shots fly over fences
+        layers.insert(FENCES_LAYER.into(), CollisionRule::tiles(collision::PLAYER | collision::MONSTER).with_props(PropRule {
+            health: 2.0,
+            shape: PropShape::Tile,
+            explosion: None,
+        }));
+        Self { layers }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CollisionRule {
+    /// Collision groups kept out of the layer's solid cells
+    pub blocks: Group,
+    /// IntGrid values that are solid. Empty makes every tile and non-zero
+    /// IntGrid cell solid.
+    pub int_values: Vec<i32>,
+    /// Breaks the layer up into props that can be destroyed, instead of one
+    /// collider
+    pub props: Option<PropRule>,
+}
+
+impl CollisionRule {
+    pub fn tiles(blocks: Group) -> Self {
+        Self { blocks, int_values: Vec::new(), props: None }
+    }
+
+    pub fn with_props(mut self, props: PropRule) -> Self {
+        self.props = Some(props);
+        self
+    }
+
+    fn is_solid(&self, value: i32) -> bool {
+        self.int_values.is_empty() || self.int_values.contains(&value)
+    }
+}
+
+/// The merged colliders of a layer's solid cells, as rectangles in the
+/// entity's space.
+#[derive(Component, Debug)]
+pub struct TerrainCollider {
+    pub blocks: Group,
+    pub rects: Vec<Rect>,
+}
+
+/// A level of an LDtk map, whose layers are spawned as its child tilemaps once
+/// the map has loaded.
+#[derive(Component)]
+pub struct LdtkLevel {
+    /// The map to take the level and tilesets from
+    pub map: Handle<LdtkMap>,
+    pub source: LevelSource,
+    /// Where to put the level's bottom left corner, instead of where the map
+    /// has it
+    pub origin: Option<Vec2>,
+}
+
+pub enum LevelSource {
+    /// A level of the map, by identifier
+    Map(String),
+    /// A level made outside LDtk, drawn with the map's tilesets
+    Generated(Box<Level>),
+}
+
+/// The extent of a spawned [`LdtkLevel`] in the world.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct LevelBounds(pub Rect);
+
+/// Despawns an [`LdtkLevel`] along with its tiles, which aren't part of the
+/// hierarchy.
+#[derive(Component)]
+pub struct Unload;
+
+/// Sent once a level's layers and entities have been spawned. They only
+/// exist after the commands are applied, at the end of [`Update`].
+#[derive(Event, Debug)]
+pub struct LevelSpawned {
+    pub entity: Entity,
+    /// The level's extent in the world
+    pub bounds: Rect,
+}
+
+/// Adds the components for an LDtk entity to a Bevy entity placed at its
+/// center, as a child of the level.
+pub type LdtkEntityFn = Box<dyn Fn(&mut EntityCommands, &EntityInstance) + Send + Sync>;
+
+/// Spawn functions for LDtk entities by identifier. Entities without one are
+/// skipped.
+#[derive(Resource, Default)]
+pub struct LdtkEntities {
+    spawners: HashMap<String, LdtkEntityFn>,
+}
+
+pub trait RegisterLdtkEntity {
+    /// Spawns LDtk entities called `identifier` through `spawn`.
+    fn register_ldtk_entity(
+        &mut self,
+        identifier: &str,
+        spawn: impl Fn(&mut EntityCommands, &EntityInstance) + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl RegisterLdtkEntity for App {
+    fn register_ldtk_entity(
+        &mut self,
+        identifier: &str,
+        spawn: impl Fn(&mut EntityCommands, &EntityInstance) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world.get_resource_or_insert_with(LdtkEntities::default).spawners.insert(identifier.into(), Box::new(spawn));
+        self
     }
 }
 
-fn load_tiles(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let texture_handle: Handle<Image> = asset_server.load(r"sprites\modern-city-assets\street.png");
-    let map_size = TilemapSize { x: 32, y: 32 };
+/// Marks an [`LdtkLevel`] whose layers have been spawned.
+#[derive(Component)]
+struct Spawned;
+
+impl LdtkLevel {
+    fn spawn(
+        mut commands: Commands,
+        levels: Query<(Entity, &LdtkLevel), (Without<Spawned>, Without<Unload>)>,
+        maps: Res<Assets<LdtkMap>>,
+        collision: Res<TileCollision>,
+        entities: Res<LdtkEntities>,
+        mut spawned: EventWriter<LevelSpawned>,
+    ) {
+        for (entity, level) in levels.iter() {
+            let Some(map) = maps.get(&level.map) else {
+                continue;
+            };
+            commands.entity(entity).insert(Spawned);
+            let ldtk_level = match &level.source {
+                LevelSource::Map(identifier) => match map.level(identifier) {
+                    Some(ldtk_level) => ldtk_level,
+                    None => {
+                        warn!("No level {identifier} in LDtk map");
+                        continue;
+                    }
+                },
+                LevelSource::Generated(ldtk_level) => ldtk_level,
+            };
+            let mut bounds = ldtk_level.bounds();
+            if let Some(origin) = level.origin {
+                bounds = Rect::from_corners(origin, origin + bounds.size());
+            }
+            commands.entity(entity).insert((Transform::from_translation(bounds.min.extend(0.0)), LevelBounds(bounds)));
+
+            let layers = ldtk_level.layer_instances.as_deref().unwrap_or_default();
+            for (depth, layer) in layers.iter().rev().enumerate() {
+                if layer.layer_type == "Entities" {
+                    spawn_entities(&mut commands, entity, layer, &entities);
+                    continue;
+                }
+                let tileset = layer.tileset_def_uid.and_then(|uid| map.tileset(uid));
+                let texture = layer.tileset_def_uid.and_then(|uid| map.tilesets.get(&uid)).filter(|_| layer.visible);
+                let tilemap = texture.map(|texture| {
+                    let beats = tileset.map(TileBeat::from_tileset).unwrap_or_default();
+                    let z = LAYER_Z + depth as f32 * LAYER_STEP;
+                    spawn_layer(&mut commands, layer, texture.clone(), &beats, z)
+                });
+
+                let colliders = match collision.layers.get(&layer.identifier) {
+                    Some(rule @ CollisionRule { props: Some(props), .. }) => {
+                        let damaged = tileset.map(damaged_tiles).unwrap_or_default();
+                        spawn_props(&mut commands, layer, rule, props, tilemap, &damaged)
+                    }
+                    Some(rule) => spawn_collider(&mut commands, layer, rule).into_iter().collect(),
+                    None => Vec::new(),
+                };
+                for child in tilemap.into_iter().chain(colliders) {
+                    commands.entity(entity).add_child(child);
+                }
+            }
+            spawned.send(LevelSpawned { entity, bounds });
+        }
+    }
+
+    fn unload(
+        mut commands: Commands,
+        levels: Query<(Entity, Option<&Children>), (With<LdtkLevel>, With<Unload>)>,
+        tilemaps: Query<&TileStorage>,
+    ) {
+        for (entity, children) in levels.iter() {
+            for &child in children.into_iter().flatten() {
+                if let Ok(storage) = tilemaps.get(child) {
+                    for tile in storage.iter().flatten() {
+                        commands.entity(*tile).despawn();
+                    }
+                }
+            }
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn spawn_entities(commands: &mut Commands, level: Entity, layer: &LayerInstance, entities: &LdtkEntities) {
+    let offset = Vec2::new(layer.px_offset_x as f32, -layer.px_offset_y as f32);
+    for instance in &layer.entity_instances {
+        let Some(spawn) = entities.spawners.get(&instance.identifier) else {
+            warn!("Nothing registered for LDtk entity {}", instance.identifier);
+            continue;
+        };
+        let position = instance.center(layer) + offset;
+        let mut entity_commands = commands.spawn(SpatialBundle::from_transform(Transform::from_translation(position.extend(0.0))));
+        spawn(&mut entity_commands, instance);
+        let entity = entity_commands.id();
+        commands.entity(level).add_child(entity);
+    }
+}
+
+fn spawn_layer(commands: &mut Commands, layer: &LayerInstance, texture: Handle<Image>, beats: &HashMap<u32, TileBeat>, z: f32) -> Entity {
+    let map_size = TilemapSize { x: layer.c_wid, y: layer.c_hei };
     let tilemap_entity = commands.spawn_empty().id();
     let mut tile_storage = TileStorage::empty(map_size);
-    let mut offset = 0;
-
-    for x in 0..map_size.x {
-        for y in 0..map_size.y {
-            let tile_pos = TilePos { x, y };
-            let tile_entity = commands
-                .spawn(TileBundle {
-                    position: tile_pos,
-                    tilemap_id: TilemapId(tilemap_entity),
-                    texture_index: TileTextureIndex(offset),
-                    ..Default::default()
-                })
-                .id();
-            tile_storage.set(&tile_pos, tile_entity);
-            offset = (offset + 1) % (15 * 15);
+
+    for tile in layer.grid_tiles.iter().chain(&layer.auto_layer_tiles) {
+        let cell = layer.tile_cell(tile);
+        let tile_pos = TilePos { x: cell.x, y: cell.y };
+        if !tile_pos.within_map_bounds(&map_size) {
+            continue;
         }
+        // Only the last of stacked tiles is kept
+        if let Some(old) = tile_storage.get(&tile_pos) {
+            commands.entity(old).despawn();
+        }
+        let color = Color::rgba(1.0, 1.0, 1.0, tile.a * layer.opacity);
+        let mut tile_commands = commands.spawn(TileBundle {
+            position: tile_pos,
+            tilemap_id: TilemapId(tilemap_entity),
+            texture_index: TileTextureIndex(tile.t),
+            flip: TileFlip { x: tile.f & 1 != 0, y: tile.f & 2 != 0, d: false },
+            color: TileColor(color),
+            ..Default::default()
+        });
+        if let Some(beat) = beats.get(&tile.t) {
+            beat.insert(&mut tile_commands, color);
+        }
+        let tile_entity = tile_commands.id();
+        tile_storage.set(&tile_pos, tile_entity);
     }
 
-    let tile_size = TilemapTileSize { x: 16.0, y: 16.0 };
-    let grid_size = tile_size.into();
-    let map_type = TilemapType::Square;
+    let grid = layer.grid_size as f32;
+    let tile_size = TilemapTileSize { x: grid, y: grid };
+    // Tile (0, 0) is centered on the tilemap's origin, so shift by half a
+    // tile to line its corner up with the level's
+    let offset = Vec2::new(layer.px_offset_x as f32, -layer.px_offset_y as f32) + grid / 2.0;
 
     commands.entity(tilemap_entity).insert(TilemapBundle {
-        grid_size,
-        map_type,
+        grid_size: tile_size.into(),
+        map_type: TilemapType::Square,
         size: map_size,
         storage: tile_storage,
-        texture: TilemapTexture::Single(texture_handle),
+        texture: TilemapTexture::Single(texture),
         tile_size,
-        transform: get_tilemap_center_transform(&map_size, &grid_size, &map_type, 0.0),
+        transform: Transform::from_translation(offset.extend(z)),
         ..Default::default()
     });
-}
\ No newline at end of file
+    tilemap_entity
+}
+
+fn spawn_collider(commands: &mut Commands, layer: &LayerInstance, rule: &CollisionRule) -> Option<Entity> {
+    let width = layer.c_wid as usize;
+    let mut solid = vec![false; width * layer.c_hei as usize];
+    let mut cells: Vec<UVec2> = layer.int_grid_cells().filter(|&(_, value)| rule.is_solid(value)).map(|(cell, _)| cell).collect();
+    if rule.int_values.is_empty() {
+        cells.extend(layer.grid_tiles.iter().chain(&layer.auto_layer_tiles).map(|tile| layer.tile_cell(tile)));
+    }
+    for cell in cells.into_iter().filter(|cell| cell.x < layer.c_wid) {
+        if let Some(solid) = solid.get_mut(cell.y as usize * width + cell.x as usize) {
+            *solid = true;
+        }
+    }
+
+    let grid = layer.grid_size as f32;
+    let rects: Vec<Rect> = merge_cells(&solid, width)
+        .into_iter()
+        .map(|rect| Rect::from_corners(rect.min.as_vec2() * grid, rect.max.as_vec2() * grid))
+        .collect();
+    if rects.is_empty() {
+        return None;
+    }
+    let shapes = rects.iter().map(|rect| (rect.center(), 0.0, Collider::cuboid(rect.half_size().x, rect.half_size().y))).collect();
+
+    let offset = Vec2::new(layer.px_offset_x as f32, -layer.px_offset_y as f32);
+    let collider = commands
+        .spawn((
+            TransformBundle::from_transform(Transform::from_translation(offset.extend(0.0))),
+            RigidBody::Fixed,
+            Collider::compound(shapes),
+            CollisionGroups::new(collision::TERRAIN, rule.blocks),
+            TerrainCollider { blocks: rule.blocks, rects },
+        ))
+        .id();
+    Some(collider)
+}
+
+/// Spawns every prop of a layer with its own collider and health, centered
+/// on its tiles.
+fn spawn_props(
+    commands: &mut Commands,
+    layer: &LayerInstance,
+    rule: &CollisionRule,
+    props: &PropRule,
+    tilemap: Option<Entity>,
+    damaged: &HashMap<u32, u32>,
+) -> Vec<Entity> {
+    let width = layer.c_wid as usize;
+    let mut tiles = vec![None; width * layer.c_hei as usize];
+    for tile in layer.grid_tiles.iter().chain(&layer.auto_layer_tiles) {
+        let cell = layer.tile_cell(tile);
+        if cell.x >= layer.c_wid {
+            continue;
+        }
+        if let Some(slot) = tiles.get_mut(cell.y as usize * width + cell.x as usize) {
+            *slot = Some(tile.t);
+        }
+    }
+
+    let grid = layer.grid_size as f32;
+    let offset = Vec2::new(layer.px_offset_x as f32, -layer.px_offset_y as f32);
+    props.shape
+        .props(&tiles, width)
+        .into_iter()
+        .map(|cells| {
+            let cell = |i: usize| UVec2::new((i % width) as u32, (i / width) as u32);
+            let (center, rects) = prop_rects(&cells, width, grid);
+            let shapes = rects.iter().map(|rect| (rect.center(), 0.0, Collider::cuboid(rect.half_size().x, rect.half_size().y))).collect();
+
+            let prop_tiles = cells
+                .iter()
+                .map(|&i| PropTile {
+                    position: TilePos { x: cell(i).x, y: cell(i).y },
+                    damaged: tiles[i].and_then(|t| damaged.get(&t).copied()),
+                })
+                .collect();
+            let position = offset + center;
+            commands
+                .spawn((
+                    TransformBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+                    RigidBody::Fixed,
+                    Collider::compound(shapes),
+                    CollisionGroups::new(collision::TERRAIN, rule.blocks),
+                    // Player attacks are fixed sensors too
+                    ActiveCollisionTypes::default() | ActiveCollisionTypes::FIXED_FIXED,
+                    TerrainCollider { blocks: rule.blocks, rects },
+                    Health::new(props.health),
+                    Prop::new(tilemap, prop_tiles, props.explosion.clone()),
+                ))
+                .id()
+        })
+        .collect()
+}
+
+/// Where the middle of a prop made of `cells` of a row-major grid is within
+/// its layer, and the rectangles covering its cells around that middle.
+fn prop_rects(cells: &[usize], width: usize, grid: f32) -> (Vec2, Vec<Rect>) {
+    let cell = |i: usize| UVec2::new((i % width) as u32, (i / width) as u32);
+    let min = cells.iter().map(|&i| cell(i)).reduce(UVec2::min).unwrap_or_default();
+    let max = cells.iter().map(|&i| cell(i)).reduce(UVec2::max).unwrap_or_default();
+    let size = max - min + UVec2::ONE;
+
+    let mut solid = vec![false; (size.x * size.y) as usize];
+    for &i in cells {
+        let local = cell(i) - min;
+        solid[(local.y * size.x + local.x) as usize] = true;
+    }
+    let center = size.as_vec2() * grid / 2.0;
+    let rects = merge_cells(&solid, size.x as usize)
+        .into_iter()
+        .map(|rect| Rect::from_corners(rect.min.as_vec2() * grid - center, rect.max.as_vec2() * grid - center))
+        .collect();
+    (min.as_vec2() * grid + center, rects)
+}
+
+/// Covers the solid cells of a row-major grid with rectangles: each run along
+/// a row is grown upwards for as long as the rows above are solid across it.
+fn merge_cells(solid: &[bool], width: usize) -> Vec<URect> {
+    let height = solid.len() / width.max(1);
+    let mut used = vec![false; solid.len()];
+    let free = |used: &[bool], x: usize, y: usize| solid[y * width + x] && !used[y * width + x];
+    let mut rects = Vec::new();
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            if !free(&used, x, y) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < width && free(&used, x, y) {
+                x += 1;
+            }
+            let mut top = y + 1;
+            while top < height && (start..x).all(|x| free(&used, x, top)) {
+                top += 1;
+            }
+            for row in y..top {
+                used[row * width + start..row * width + x].fill(true);
+            }
+            rects.push(URect::new(start as u32, y as u32, x as u32, top as u32));
+        }
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> (Vec<bool>, usize) {
+        (rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect(), rows[0].len())
+    }
+
+    #[test]
+    fn solid_block_is_one_rect() {
+        let (solid, width) = grid(&["###", "###"]);
+        assert_eq!(merge_cells(&solid, width), vec![URect::new(0, 0, 3, 2)]);
+        assert!(merge_cells(&[false; 6], 3).is_empty());
+    }
+
+    #[test]
+    fn rows_grow_while_the_next_row_is_solid_across() {
+        let (solid, width) = grid(&["###", "#..", "#.#"]);
+        assert_eq!(merge_cells(&solid, width), vec![
+            URect::new(0, 0, 3, 1),
+            URect::new(0, 1, 1, 3),
+            URect::new(2, 2, 3, 3),
+        ]);
+    }
+
+    /// The layer's tilemap draws cell (x, y) from (x, y) * grid to one grid
+    /// further, so a prop's collider has to cover exactly those squares.
+    #[test]
+    fn prop_collider_lines_up_with_its_tiles() {
+        let grid = 16.0;
+        let width = 5;
+        // An L of tiles away from the layer's corner
+        let cells = [7, 8, 9, 12, 17];
+        let (center, rects) = prop_rects(&cells, width, grid);
+        assert_eq!(center, Vec2::new(56.0, 40.0));
+
+        let area: f32 = rects.iter().map(|rect| rect.width() * rect.height()).sum();
+        assert_eq!(area, cells.len() as f32 * grid * grid);
+        for &i in &cells {
+            let tile = Vec2::new((i % width) as f32, (i / width) as f32) * grid;
+            let covered = rects.iter().any(|rect| {
+                let rect = Rect::from_corners(rect.min + center, rect.max + center);
+                rect.contains(tile) && rect.contains(tile + grid)
+            });
+            assert!(covered, "tile {i} at {tile} isn't covered");
+        }
+    }
+
+    #[test]
+    fn every_solid_cell_is_covered_once() {
+        let (solid, width) = grid(&["##.##.", "####..", ".##..#", "####.#"]);
+        let mut covered = vec![0; solid.len()];
+        for rect in merge_cells(&solid, width) {
+            for y in rect.min.y..rect.max.y {
+                for x in rect.min.x..rect.max.x {
+                    covered[y as usize * width + x as usize] += 1;
+                }
+            }
+        }
+        assert_eq!(covered, solid.iter().map(|&solid| solid as i32).collect::<Vec<_>>());
+    }
+}
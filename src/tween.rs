@@ -0,0 +1,338 @@
+use std::f32::consts::PI;
+
+use bevy::reflect::Reflect;
+use serde::{Deserialize, Serialize};
+
+/// A value that changes over the course of a beat.
+///
+/// `p` runs from 0 to 1 over the beat; `start` and `end` pick the part of the
+/// beat the tween plays in, holding its first value before and its last value
+/// after. `mid` is where a Triangle peaks and a Square switches from `a` to `b`.
+#[derive(Clone, Debug, Reflect, Serialize, Deserialize)]
+#[reflect(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tween {
+    pub ttype: TweenType,
+    pub a: f32,
+    pub b: f32,
+    pub start: f32,
+    pub mid: f32,
+    pub end: f32,
+    pub ease: Ease,
+}
+
+impl Default for Tween {
+    fn default() -> Self {
+        Self { ttype: TweenType::Sawtooth, a: 1.0, b: 1.0, start: 0.0, mid: 0.5, end: 1.0, ease: Ease::Linear }
+    }
+}
+
+#[derive(Clone, Debug, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+pub enum TweenType {
+    /// `a` to `b`
+    Sawtooth,
+    /// `a` to `b` and back again
+    Triangle,
+    /// `a`, then `b`
+    Square,
+    /// Through each keyframe in turn, ignoring `a` and `b`
+    Keyframes(Vec<Keyframe>),
+}
+
+#[derive(Copy, Clone, Debug, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+pub struct Keyframe {
+    pub at: f32,
+    pub value: f32,
+    /// Easing of the stretch leading up to this keyframe
+    #[serde(default)]
+    pub ease: Ease,
+}
+
+impl Keyframe {
+    pub fn new(at: f32, value: f32, ease: Ease) -> Self {
+        Self { at, value, ease }
+    }
+}
+
+impl Tween {
+    pub fn tween(&self, p: f32) -> f32 {
+        let t = if self.end > self.start {
+            ((p - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+        } else if p < self.start {
+            0.0
+        } else {
+            1.0
+        };
+        match &self.ttype {
+            TweenType::Sawtooth =>
+                lerp(self.a, self.b, self.ease.apply(t)),
+            TweenType::Triangle =>
+                if t < self.mid || self.mid >= 1.0 {
+                    lerp(self.a, self.b, self.ease.apply(t / self.mid))
+                } else {
+                    lerp(self.b, self.a, self.ease.apply((t - self.mid) / (1.0 - self.mid)))
+                }
+            TweenType::Square =>
+                if t < self.mid { self.a } else { self.b },
+            TweenType::Keyframes(keys) =>
+                keyframes(keys, t),
+        }
+    }
+}
+
+fn keyframes(keys: &[Keyframe], t: f32) -> f32 {
+    let Some(first) = keys.first() else {
+        return 0.0;
+    };
+    if t <= first.at {
+        return first.value;
+    }
+    for pair in keys.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if t <= to.at {
+            let span = to.at - from.at;
+            let local = if span > 0.0 { (t - from.at) / span } else { 1.0 };
+            return lerp(from.value, to.value, to.ease.apply(local));
+        }
+    }
+    keys[keys.len() - 1].value
+}
+
+fn lerp(s: f32, e: f32, p: f32) -> f32 {
+    s + p * (e - s)
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Default, Serialize, Deserialize)]
+pub enum Ease {
+    #[default]
+    Linear,
+    In(Curve),
+    Out(Curve),
+    InOut(Curve),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+pub enum Curve {
+    Quad,
+    Cubic,
+    Expo,
+    Elastic,
+    Back,
+    Bounce,
+}
+
+impl Ease {
+    /// Maps progress `t` in 0..=1 onto the curve. Always starts at 0 and ends
+    /// at 1, though Elastic and Back overshoot along the way.
+    pub fn apply(self, t: f32) -> f32 {
+        if t <= 0.0 {
+            return 0.0;
+        }
+        if t >= 1.0 {
+            return 1.0;
+        }
+        match self {
+            Ease::Linear => t,
+            Ease::In(curve) => curve.ease_in(t),
+            Ease::Out(curve) => 1.0 - curve.ease_in(1.0 - t),
+            Ease::InOut(curve) =>
+                if t < 0.5 {
+                    curve.ease_in(2.0 * t) / 2.0
+                } else {
+                    1.0 - curve.ease_in(2.0 - 2.0 * t) / 2.0
+                }
+        }
+    }
+}
+
+impl Curve {
+    fn ease_in(self, t: f32) -> f32 {
+        match self {
+            Curve::Quad => t * t,
+            Curve::Cubic => t * t * t,
+            Curve::Expo => 2f32.powf(10.0 * t - 10.0),
+            Curve::Elastic =>
+                -(2f32.powf(10.0 * t - 10.0)) * ((10.0 * t - 10.75) * (2.0 * PI / 3.0)).sin(),
+            Curve::Back => {
+                const C1: f32 = 1.70158;
+                (C1 + 1.0) * t * t * t - C1 * t * t
+            }
+            Curve::Bounce => 1.0 - bounce_out(1.0 - t),
+        }
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const N1: f32 = 7.5625;
+    const D1: f32 = 2.75;
+    if t < 1.0 / D1 {
+        N1 * t * t
+    } else if t < 2.0 / D1 {
+        let t = t - 1.5 / D1;
+        N1 * t * t + 0.75
+    } else if t < 2.5 / D1 {
+        let t = t - 2.25 / D1;
+        N1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D1;
+        N1 * t * t + 0.984375
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 6] = [Curve::Quad, Curve::Cubic, Curve::Expo, Curve::Elastic, Curve::Back, Curve::Bounce];
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {expected}, got {actual}");
+    }
+
+    fn tween(ttype: TweenType) -> Tween {
+        Tween { ttype, a: 2.0, b: 10.0, ..Tween::default() }
+    }
+
+    #[test]
+    fn sawtooth_goes_from_a_to_b() {
+        let t = tween(TweenType::Sawtooth);
+        assert_close(t.tween(0.0), 2.0);
+        assert_close(t.tween(0.25), 4.0);
+        assert_close(t.tween(1.0), 10.0);
+    }
+
+    #[test]
+    fn triangle_returns_to_a() {
+        let t = tween(TweenType::Triangle);
+        assert_close(t.tween(0.0), 2.0);
+        assert_close(t.tween(0.25), 6.0);
+        assert_close(t.tween(0.5), 10.0);
+        assert_close(t.tween(0.75), 6.0);
+        assert_close(t.tween(1.0), 2.0);
+    }
+
+    #[test]
+    fn triangle_with_early_peak() {
+        let t = Tween { mid: 0.2, ..tween(TweenType::Triangle) };
+        assert_close(t.tween(0.1), 6.0);
+        assert_close(t.tween(0.2), 10.0);
+        assert_close(t.tween(0.6), 6.0);
+        assert_close(t.tween(1.0), 2.0);
+    }
+
+    #[test]
+    fn square_switches_at_mid() {
+        let t = Tween { mid: 0.3, ..tween(TweenType::Square) };
+        assert_close(t.tween(0.29), 2.0);
+        assert_close(t.tween(0.3), 10.0);
+        assert_close(t.tween(1.0), 10.0);
+    }
+
+    #[test]
+    fn start_and_end_remap_progress() {
+        let t = Tween { start: 0.5, end: 0.9, ..tween(TweenType::Sawtooth) };
+        assert_close(t.tween(0.2), 2.0);
+        assert_close(t.tween(0.5), 2.0);
+        assert_close(t.tween(0.7), 6.0);
+        assert_close(t.tween(0.9), 10.0);
+        assert_close(t.tween(1.0), 10.0);
+
+        let t = Tween { start: 0.5, ..tween(TweenType::Triangle) };
+        assert_close(t.tween(0.75), 10.0);
+        assert_close(t.tween(1.0), 2.0);
+
+        let t = Tween { start: 0.5, ..tween(TweenType::Square) };
+        assert_close(t.tween(0.7), 2.0);
+        assert_close(t.tween(0.8), 10.0);
+    }
+
+    #[test]
+    fn eased_sawtooth() {
+        let t = Tween { ease: Ease::In(Curve::Quad), ..tween(TweenType::Sawtooth) };
+        assert_close(t.tween(0.5), 4.0);
+        let t = Tween { ease: Ease::Out(Curve::Quad), ..tween(TweenType::Sawtooth) };
+        assert_close(t.tween(0.5), 8.0);
+    }
+
+    #[test]
+    fn keyframes() {
+        let t = tween(TweenType::Keyframes(vec![
+            Keyframe::new(0.0, 1.0, Ease::Linear),
+            Keyframe::new(0.5, 3.0, Ease::Linear),
+            Keyframe::new(0.75, 3.0, Ease::Linear),
+            Keyframe::new(1.0, -1.0, Ease::In(Curve::Quad)),
+        ]));
+        assert_close(t.tween(0.0), 1.0);
+        assert_close(t.tween(0.25), 2.0);
+        assert_close(t.tween(0.6), 3.0);
+        assert_close(t.tween(0.875), 2.0);
+        assert_close(t.tween(1.0), -1.0);
+    }
+
+    #[test]
+    fn keyframes_hold_outside_their_range() {
+        let t = tween(TweenType::Keyframes(vec![
+            Keyframe::new(0.2, 5.0, Ease::Linear),
+            Keyframe::new(0.8, 7.0, Ease::Linear),
+        ]));
+        assert_close(t.tween(0.0), 5.0);
+        assert_close(t.tween(0.5), 6.0);
+        assert_close(t.tween(1.0), 7.0);
+        assert_close(tween(TweenType::Keyframes(vec![])).tween(0.5), 0.0);
+    }
+
+    #[test]
+    fn every_ease_starts_at_zero_and_ends_at_one() {
+        for curve in CURVES {
+            for ease in [Ease::In(curve), Ease::Out(curve), Ease::InOut(curve)] {
+                assert_close(ease.apply(0.0), 0.0);
+                assert_close(ease.apply(1.0), 1.0);
+            }
+        }
+        assert_close(Ease::Linear.apply(0.3), 0.3);
+    }
+
+    #[test]
+    fn in_out_is_symmetric_about_the_middle() {
+        for curve in CURVES {
+            let ease = Ease::InOut(curve);
+            assert_close(ease.apply(0.5), 0.5);
+            for t in [0.1, 0.2, 0.3, 0.4] {
+                assert_close(ease.apply(t) + ease.apply(1.0 - t), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn out_mirrors_in() {
+        for curve in CURVES {
+            for t in [0.1, 0.25, 0.5, 0.75, 0.9] {
+                assert_close(Ease::Out(curve).apply(t), 1.0 - Ease::In(curve).apply(1.0 - t));
+            }
+        }
+    }
+
+    #[test]
+    fn curve_values() {
+        assert_close(Ease::In(Curve::Quad).apply(0.5), 0.25);
+        assert_close(Ease::In(Curve::Cubic).apply(0.5), 0.125);
+        assert_close(Ease::InOut(Curve::Cubic).apply(0.25), 0.0625);
+        assert_close(Ease::In(Curve::Expo).apply(0.5), 2f32.powf(-5.0));
+        assert_close(Ease::Out(Curve::Expo).apply(0.5), 1.0 - 2f32.powf(-5.0));
+        assert_close(Ease::Out(Curve::Bounce).apply(1.0 / 2.75), 1.0);
+        assert_close(Ease::Out(Curve::Bounce).apply(0.5), 0.765625);
+        assert_close(Ease::In(Curve::Bounce).apply(0.5), 0.234375);
+    }
+
+    #[test]
+    fn back_and_elastic_overshoot() {
+        assert!(Ease::In(Curve::Back).apply(0.2) < 0.0);
+        assert!(Ease::Out(Curve::Back).apply(0.8) > 1.0);
+        assert!(Ease::In(Curve::Elastic).apply(0.9) < 0.0);
+        assert!(Ease::Out(Curve::Elastic).apply(0.1) > 1.0);
+    }
+}